@@ -39,7 +39,7 @@ class ChatApi {
         Uri.parse('$_baseUrl/v1/chat/completions'),
         headers: _headers,
         body: jsonEncode({
-          'model': 'r1sonnet',
+          'model': 'dualmind',
           'messages': messages.map((msg) => msg.toJson()).toList(),
           'stream': false,
         }),
@@ -0,0 +1,354 @@
+//! Response cache for repeated identical requests
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::Config;
+use crate::config::cache::{CacheBackend, CacheConfig};
+use crate::core::pipeline::CritiqueRound;
+use crate::models::Message;
+
+/// Header reporting whether a response was served from the cache
+pub const CACHE_STATUS_HEADER: &str = "X-DualMind-Cache";
+
+/// The parts of a completed pipeline run worth replaying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub reasoning: String,
+    pub answer: String,
+    #[serde(default)]
+    pub critiques: Vec<CritiqueRound>,
+}
+
+/// How a request was handled by the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+}
+
+/// Result of looking a request up in the cache
+pub enum CacheLookup {
+    Hit(CachedResponse),
+    /// Not cached; store the response under this key once it is produced
+    Miss(String),
+    Bypass,
+}
+
+impl CacheLookup {
+    pub fn status(&self) -> CacheStatus {
+        match self {
+            CacheLookup::Hit(_) => CacheStatus::Hit,
+            CacheLookup::Miss(_) => CacheStatus::Miss,
+            CacheLookup::Bypass => CacheStatus::Bypass,
+        }
+    }
+}
+
+/// Counters exposed on `/metrics`
+#[derive(Debug, Default)]
+struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bypassed: AtomicU64,
+    stores: AtomicU64,
+}
+
+/// Cache of final answers keyed on the request that produced them
+pub struct ResponseCache {
+    config: CacheConfig,
+    memory: Mutex<LruCache<String, CachedResponse>>,
+    metrics: CacheMetrics,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Result<Self, String> {
+        let capacity = NonZeroUsize::new(config.capacity)
+            .ok_or_else(|| "Cache capacity must be greater than zero".to_string())?;
+        if config.backend == CacheBackend::Disk {
+            std::fs::create_dir_all(&config.dir)
+                .map_err(|e| format!("Failed to create cache directory {}: {}", config.dir, e))?;
+        }
+
+        Ok(Self {
+            config,
+            memory: Mutex::new(LruCache::new(capacity)),
+            metrics: CacheMetrics::default(),
+        })
+    }
+
+    /// Look up a request run with `pipeline_config`, counting the outcome
+    ///
+    /// Requests sampled above zero temperature bypass the cache unless
+    /// `cache_nonzero_temperature` is set, as do requests that ask for a
+    /// fresh answer with `Cache-Control: no-cache` or `no-store`.
+    pub fn lookup(
+        &self,
+        pipeline: &str,
+        messages: &[Message],
+        pipeline_config: &Config,
+        max_tokens: Option<i32>,
+        no_cache: bool,
+    ) -> CacheLookup {
+        if no_cache || (pipeline_config.temperature > 0.0 && !self.config.cache_nonzero_temperature) {
+            self.metrics.bypassed.fetch_add(1, Ordering::Relaxed);
+            return CacheLookup::Bypass;
+        }
+
+        let key = cache_key(pipeline, messages, pipeline_config, max_tokens);
+        match self.get(&key) {
+            Some(response) => {
+                self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+                CacheLookup::Hit(response)
+            }
+            None => {
+                self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+                CacheLookup::Miss(key)
+            }
+        }
+    }
+
+    /// Store the response for a key returned by a missed lookup
+    pub fn store(&self, key: &str, response: CachedResponse) {
+        match self.config.backend {
+            CacheBackend::Memory => {
+                self.memory.lock().unwrap().put(key.to_string(), response);
+            }
+            CacheBackend::Disk => {
+                let result = serde_json::to_string(&response)
+                    .map_err(|e| e.to_string())
+                    .and_then(|contents| std::fs::write(self.entry_path(key), contents).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Cache: failed to store entry {}: {}", key, e);
+                    return;
+                }
+                self.prune_disk();
+            }
+        }
+        self.metrics.stores.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        match self.config.backend {
+            CacheBackend::Memory => self.memory.lock().unwrap().get(key).cloned(),
+            CacheBackend::Disk => {
+                let contents = std::fs::read_to_string(self.entry_path(key)).ok()?;
+                serde_json::from_str(&contents).ok()
+            }
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        Path::new(&self.config.dir).join(format!("{}.json", key))
+    }
+
+    /// The entry files in the disk cache directory
+    fn disk_entries(&self) -> Vec<std::fs::DirEntry> {
+        let Ok(entries) = std::fs::read_dir(&self.config.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .collect()
+    }
+
+    /// Remove the least recently written entries beyond the capacity
+    fn prune_disk(&self) {
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = self
+            .disk_entries()
+            .into_iter()
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+            .collect();
+        if files.len() <= self.config.capacity {
+            return;
+        }
+
+        files.sort();
+        let excess = files.len() - self.config.capacity;
+        for (_, path) in files.into_iter().take(excess) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn entries(&self) -> usize {
+        match self.config.backend {
+            CacheBackend::Memory => self.memory.lock().unwrap().len(),
+            CacheBackend::Disk => self.disk_entries().len(),
+        }
+    }
+
+    /// Cache counters in the Prometheus text format
+    pub fn prometheus_metrics(&self) -> String {
+        let counters = [
+            ("dualmind_cache_hits_total", "Requests served from the response cache", &self.metrics.hits),
+            ("dualmind_cache_misses_total", "Cacheable requests not found in the cache", &self.metrics.misses),
+            ("dualmind_cache_bypassed_total", "Requests that skipped the cache", &self.metrics.bypassed),
+            ("dualmind_cache_stores_total", "Responses written to the cache", &self.metrics.stores),
+        ];
+
+        let mut output = String::new();
+        for (name, help, value) in counters {
+            output.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n",
+                value.load(Ordering::Relaxed)
+            ));
+        }
+        output.push_str(&format!(
+            "# HELP dualmind_cache_entries Responses currently cached\n# TYPE dualmind_cache_entries gauge\ndualmind_cache_entries {}\n",
+            self.entries()
+        ));
+        output
+    }
+}
+
+/// Hash the pipeline, normalised messages, sampling parameters and reasoning settings
+fn cache_key(pipeline: &str, messages: &[Message], config: &Config, max_tokens: Option<i32>) -> String {
+    let messages: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|m| {
+            json!({
+                "role": m.role.to_string(),
+                "content": m.content.replace("\r\n", "\n").trim(),
+            })
+        })
+        .collect();
+    let budget = &config.reasoning_budget;
+    let key = json!({
+        "pipeline": pipeline,
+        "messages": messages,
+        "temperature": config.temperature,
+        "max_tokens": max_tokens,
+        "reasoning_mode": config.reasoning_mode.as_str(),
+        "reasoning_effort": budget.effort.map(|e| e.as_str()),
+        "reasoning_max_tokens": budget.max_tokens,
+        "reasoning_max_seconds": budget.max_seconds,
+    });
+
+    format!("{:x}", Sha256::digest(key.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::adaptive::{ReasoningEffort, ReasoningMode};
+    use crate::models::Role;
+    use crate::testing::test_config;
+
+    /// The test configuration sampled at `temperature`
+    fn config(temperature: f32) -> Config {
+        let mut config = test_config("http://127.0.0.1:9");
+        config.temperature = temperature;
+        config
+    }
+
+    fn messages(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: Role::User,
+            content: content.to_string(),
+        }]
+    }
+
+    fn response(answer: &str) -> CachedResponse {
+        CachedResponse {
+            reasoning: String::new(),
+            answer: answer.to_string(),
+            critiques: Vec::new(),
+        }
+    }
+
+    fn miss_key(lookup: CacheLookup) -> String {
+        match lookup {
+            CacheLookup::Miss(key) => key,
+            other => panic!("expected a miss, got {:?}", other.status()),
+        }
+    }
+
+    #[test]
+    fn stored_responses_are_hits() {
+        let cache = ResponseCache::new(CacheConfig::default()).unwrap();
+        let key = miss_key(cache.lookup("dualmind", &messages("hi"), &config(0.0), None, false));
+        cache.store(&key, response("hello"));
+
+        match cache.lookup("dualmind", &messages("hi"), &config(0.0), None, false) {
+            CacheLookup::Hit(cached) => assert_eq!(cached.answer, "hello"),
+            other => panic!("expected a hit, got {:?}", other.status()),
+        }
+    }
+
+    #[test]
+    fn nonzero_temperature_and_no_cache_bypass() {
+        let cache = ResponseCache::new(CacheConfig::default()).unwrap();
+        assert_eq!(cache.lookup("dualmind", &messages("hi"), &config(0.7), None, false).status(), CacheStatus::Bypass);
+        assert_eq!(cache.lookup("dualmind", &messages("hi"), &config(0.0), None, true).status(), CacheStatus::Bypass);
+
+        let cache = ResponseCache::new(CacheConfig {
+            cache_nonzero_temperature: true,
+            ..CacheConfig::default()
+        })
+        .unwrap();
+        assert_eq!(cache.lookup("dualmind", &messages("hi"), &config(0.7), None, false).status(), CacheStatus::Miss);
+    }
+
+    #[test]
+    fn disk_entries_ignore_stray_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(CacheConfig {
+            backend: CacheBackend::Disk,
+            dir: dir.path().to_string_lossy().into_owned(),
+            ..CacheConfig::default()
+        })
+        .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "stray").unwrap();
+        std::fs::create_dir(dir.path().join("nested.json")).unwrap();
+
+        let key = miss_key(cache.lookup("dualmind", &messages("hi"), &config(0.0), None, false));
+        cache.store(&key, response("hello"));
+        assert_eq!(cache.entries(), 1);
+        assert!(cache.prometheus_metrics().contains("dualmind_cache_entries 1\n"));
+        assert_eq!(cache.lookup("dualmind", &messages("hi"), &config(0.0), None, false).status(), CacheStatus::Hit);
+    }
+
+    #[test]
+    fn key_covers_the_request() {
+        let base = cache_key("dualmind", &messages("hi"), &config(0.0), None);
+        assert_eq!(base, cache_key("dualmind", &messages("  hi\r\n"), &config(0.0), None));
+        assert_ne!(base, cache_key("other", &messages("hi"), &config(0.0), None));
+        assert_ne!(base, cache_key("dualmind", &messages("hello"), &config(0.0), None));
+        assert_ne!(base, cache_key("dualmind", &messages("hi"), &config(0.5), None));
+        assert_ne!(base, cache_key("dualmind", &messages("hi"), &config(0.0), Some(100)));
+    }
+
+    #[test]
+    fn key_covers_the_reasoning_settings() {
+        let base = cache_key("dualmind", &messages("hi"), &config(0.0), None);
+        let key_with = |change: fn(&mut Config)| {
+            let mut config = config(0.0);
+            change(&mut config);
+            cache_key("dualmind", &messages("hi"), &config, None)
+        };
+
+        assert_ne!(base, key_with(|c| c.reasoning_mode = ReasoningMode::Skip));
+        assert_ne!(base, key_with(|c| c.reasoning_budget.effort = Some(ReasoningEffort::Low)));
+        assert_ne!(base, key_with(|c| c.reasoning_budget.max_tokens = Some(256)));
+        assert_ne!(base, key_with(|c| c.reasoning_budget.max_seconds = Some(10)));
+    }
+}
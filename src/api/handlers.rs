@@ -9,27 +9,23 @@ use axum::{
 };
 use chrono;
 use futures::StreamExt;
-use reqwest::Client;
 use serde_json::json;
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::Arc,
     time::Instant,
 };
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
 
+use crate::api::cache::{CacheLookup, CachedResponse, CACHE_STATUS_HEADER};
 use crate::api::models::ChatCompletionRequest;
 use crate::api::server::{AppState, cleanup_old_sessions};
-use crate::config::Config;
-use crate::core::llm::{
-    call_crafter_with_context,
-    clean_response_text,
-    is_coding_request,
-    process_reasoner_call,
-};
-use crate::streaming::process_stream;
+use crate::config::{Config, Pipeline};
+use crate::core::llm::clean_response_text;
+use crate::core::pipeline::{run_pipeline, PipelineEvent, PipelineOutput, StageOutput};
+use crate::error::{Error, ErrorKind};
+use crate::models::openai::{ChatResponse, Usage};
 use crate::models::{Message, Role};
 use crate::config::aisettings;
 
@@ -42,10 +38,23 @@ pub async fn chat_completions(
     println!("Received API request: {:?}", request);
     println!("Request headers: {:?}", headers);
 
+    // Route the request to the pipeline named by `model`
+    let mut config = match state.config.find_pipeline(&request.model) {
+        Some(pipeline) => state.config.for_pipeline(pipeline),
+        None => return model_not_found_response(&request.model),
+    };
+    if let Some(temperature) = request.temperature {
+        config.temperature = temperature;
+    }
+    if let Some(mode) = request.reasoning_mode {
+        config.reasoning_mode = mode;
+    }
+    request.apply_reasoning_budget(&mut config.reasoning_budget);
+
     // Handle streaming and non-streaming differently
     if request.stream {
         // For streaming requests, we need to return a proper SSE stream
-        return handle_streaming_request(state, headers, request).await;
+        return handle_streaming_request(state, headers, request, config).await;
     }
 
     // Extract session ID from header or generate new one for non-streaming requests
@@ -57,133 +66,71 @@ pub async fn chat_completions(
 
     println!("Using session ID: {}", session_id);
 
-    // Perform cleanup if needed
-    {
-        let mut last_cleanup = state.last_cleanup.lock().unwrap();
-        let now = Instant::now();
-        if now.duration_since(*last_cleanup) > std::time::Duration::from_secs(60) {
-            // Cleanup every minute
-            let mut sessions = state.sessions.lock().unwrap();
-            cleanup_old_sessions(&mut sessions);
-            *last_cleanup = now;
-        }
-    }
-
-    // Get or create session
-    let mut session_messages = {
-        let mut sessions = state.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(&session_id) {
-            // Update existing session
-            session.last_active = Instant::now();
-            session.messages.clone()
-        } else {
-            // Create new session
-            let new_session = crate::api::server::ChatSession {
-                messages: Vec::new(),
-                last_active: Instant::now(),
-            };
-            sessions.insert(session_id.clone(), new_session);
-            Vec::new()
-        }
-    };
-
-    // Add the new messages to the session context
-    for message in &request.messages {
-        session_messages.push(message.clone());
-    }
+    // The session's history followed by the new messages
+    let session_messages = session_context(&state, &session_id, &request.messages);
 
     // Add debug logging to track the user content
     println!("Debug - Request messages: {:?}", request.messages);
 
     // Extract the last user message
     let user_content = request.messages.iter()
-        .filter(|m| m.role == Role::User)
-        .last()
+        .rfind(|m| m.role == Role::User)
         .map(|m| m.content.clone())
         .unwrap_or_default();
 
     println!("Debug - Extracted user content: '{}'", user_content);
 
-    // Process with reasoning model first
-    println!("API: Starting thinking phase with {}...", state.config.reasoning_model);
-    let reasoning =
-        match process_reasoner_call(&state.client, &state.config, &user_content).await {
-            Ok(result) => {
-                println!("API: Thinking phase completed successfully");
-                result
-            }
-            Err(e) => {
-                println!("API: Error in thinking phase: {}", e);
-                return build_error_response(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    &format!("Error in thinking phase: {}", e),
-                    "api_error",
-                );
-            }
-        };
-
-    // Check if this is a coding request
-    let is_coding = is_coding_request(&user_content);
+    // Serve repeated requests from the cache
+    let cache_lookup = lookup_cache(&state, &headers, &request, &config, &session_messages);
+    let cache_status = cache_lookup.status();
 
-    // Process with crafting model for final response
-    if is_coding {
-        println!("API: Starting execution phase with {}...", state.config.craft_model);
-    } else {
-        println!("API: Starting response phase with {}...", state.config.craft_model);
-    }
-    let mut final_response = match call_crafter_with_context(
-        &state.client,
-        &state.config.api_key,
-        &session_messages,
-        &reasoning,
-        &state.config,
-    )
-    .await
-    {
-        Ok(result) => result,
-        Err(e) => {
-            return build_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("Error in execution phase: {}", e),
-                "api_error",
-            );
+    // Run the pipeline over the session
+    let (mut final_response, critiques) = match cache_lookup {
+        CacheLookup::Hit(cached) => {
+            println!("API: Serving cached response");
+            (cached.answer, cached.critiques)
+        }
+        lookup => {
+            println!("API: Starting pipeline with {} stages...", config.pipeline_stages().len());
+            let previous_reasoning = session_reasoning(&state, &session_id);
+            match run_pipeline(&state.client, &config, &session_messages, &previous_reasoning, None).await {
+                Ok(output) => {
+                    println!("API: Pipeline completed successfully ({} reasoning)", output.reasoning_mode);
+                    store_in_cache(&state, &lookup, &output);
+                    set_session_reasoning(&state, &session_id, &output);
+                    (output.answer().to_string(), output.critiques)
+                }
+                Err(e) => {
+                    println!("API: {}", e);
+                    return build_error_response(&e);
+                }
+            }
         }
     };
 
     // Clean up the response
     final_response = clean_response_text(&final_response);
-    // Add assistant response to session history
-    {
-        let mut sessions = state.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(&session_id) {
-            session.messages.push(Message {
-                role: Role::Assistant,
-                content: final_response.clone(),
-            });
-        }
-    }
+    record_turn(&state, &session_id, &session_messages, final_response.clone());
 
     // Create response object - match OpenAI exactly
-    let response_json = serde_json::json!({
-        "id": format!("chatcmpl-{}", Uuid::new_v4().simple().to_string()),
-        "object": "chat.completion",
-        "created": chrono::Utc::now().timestamp(),
-        "model": request.model,
-        "choices": [{
-            "index": 0,
-            "message": {
-                "role": "assistant",
-                "content": final_response
-            },
-            "finish_reason": "stop"
-        }],
-        "usage": {
-            "prompt_tokens": 100,
-            "completion_tokens": 100,
-            "total_tokens": 200
-        }
+    let mut response_json = ChatResponse::new(
+        format!("chatcmpl-{}", Uuid::new_v4().simple()),
+        chrono::Utc::now().timestamp(),
+        request.model.clone(),
+        final_response.clone(),
+    );
+    response_json.usage = Some(Usage {
+        prompt_tokens: 100,
+        completion_tokens: 100,
+        total_tokens: 200,
+        ..Default::default()
     });
 
+    // Attach the critique trail when the critique pass ran
+    if !critiques.is_empty() {
+        response_json.extra.insert("critique".to_string(), json!(critiques));
+    }
+
     println!("Sending response with content: {}", final_response);
     println!(
         "Full response JSON: {}",
@@ -191,7 +138,7 @@ pub async fn chat_completions(
     );
 
     // Create a response with proper headers
-    let response = axum::response::Response::builder()
+    let mut response = axum::response::Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
@@ -207,13 +154,15 @@ pub async fn chat_completions(
         .header("X-Request-ID", Uuid::new_v4().to_string())
         .header("X-Session-ID", session_id) // Return session ID so clients can reuse it
         .header("HTTP-Referer", "https://app.dualmind.ai")
-        .header("X-Title", "DualMind API Client")
+        .header("X-Title", "DualMind API Client");
+    if state.cache.is_some() {
+        response = response.header(CACHE_STATUS_HEADER, cache_status.as_str());
+    }
+    response
         .body(axum::body::Body::from(
             serde_json::to_string(&response_json).unwrap(),
         ))
-        .unwrap();
-
-    response
+        .unwrap()
 }
 
 /// Handle streaming requests
@@ -221,6 +170,7 @@ async fn handle_streaming_request(
     state: Arc<AppState>,
     headers: HeaderMap,
     request: ChatCompletionRequest,
+    config: Config,
 ) -> axum::response::Response<Body> {
     println!("Handling streaming request");
 
@@ -231,6 +181,16 @@ async fn handle_streaming_request(
         .map(|s| s.to_string())
         .unwrap_or_else(|| Uuid::new_v4().to_string());
 
+    // The session's history followed by the new messages
+    let session_messages = session_context(&state, &session_id, &request.messages);
+
+    // Add debug logging to track the user content
+    println!("Debug - Request messages: {:?}", request.messages);
+
+    // Serve repeated requests from the cache
+    let cache_lookup = lookup_cache(&state, &headers, &request, &config, &session_messages);
+    let cache_status = cache_lookup.status();
+
     // Create a channel to send SSE events
     let (tx, rx) = mpsc::channel(100);
 
@@ -239,21 +199,20 @@ async fn handle_streaming_request(
 
     // Process the request on the current task (non-async)
     tokio::task::spawn(handle_stream_processing(
-        state.client.clone(),
-        state.config.api_key.clone(),
-        Arc::clone(&state.sessions),
+        Arc::clone(&state),
         session_id.clone(),
         request.model.clone(),
-        request.messages.clone(),
+        session_messages,
+        cache_lookup,
         tx,
-        state.config.clone(),
+        config,
     ));
 
     // Convert to body
     let body = Body::from_stream(stream.map(Ok::<_, std::convert::Infallible>));
 
     // Build response with proper headers
-    axum::response::Response::builder()
+    let mut response = axum::response::Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "text/event-stream")
         .header("Cache-Control", "no-cache")
@@ -262,292 +221,301 @@ async fn handle_streaming_request(
         .header("X-Session-ID", session_id)
         .header("X-API-Provider", if state.config.api_url.contains("openrouter") { "OpenRouter" } else { "LiteLLM" })
         .header("HTTP-Referer", "https://app.dualmind.ai")
-        .header("X-Title", "DualMind API Client")
-        .body(body)
-        .unwrap()
+        .header("X-Title", "DualMind API Client");
+    if state.cache.is_some() {
+        response = response.header(CACHE_STATUS_HEADER, cache_status.as_str());
+    }
+    response.body(body).unwrap()
 }
 
 /// Process a streaming request
 async fn handle_stream_processing(
-    client: Client,
-    api_key: String,
-    sessions: Arc<Mutex<HashMap<String, crate::api::server::ChatSession>>>,
+    state: Arc<AppState>,
     session_id: String,
-    model: String,
-    messages: Vec<Message>,
+    model_id: String,
+    session_messages: Vec<Message>,
+    cache_lookup: CacheLookup,
     tx: mpsc::Sender<String>,
     config: Config,
 ) {
-    println!("Using model: {}", config.craft_model);
-    println!("API URL: {}", config.api_url);
+    let client = &state.client;
+    let config = &config;
 
-    // Get or create session
-    let mut session_messages = {
-        let mut sessions_lock = sessions.lock().unwrap();
-        if let Some(session) = sessions_lock.get_mut(&session_id) {
-            // Update existing session
-            session.last_active = Instant::now();
-            session.messages.clone()
-        } else {
-            // Create new session
-            let new_session = crate::api::server::ChatSession {
-                messages: Vec::new(),
-                last_active: Instant::now(),
-            };
-            sessions_lock.insert(session_id.clone(), new_session);
-            Vec::new()
-        }
-    };
-
-    // Add the new messages to the session context
-    for message in &messages {
-        session_messages.push(message.clone());
-    }
-
-    // Add debug logging to track the user content
-    println!("Debug - Request messages: {:?}", messages);
+    println!("Using pipeline: {}", model_id);
+    println!("API URL: {}", config.api_url);
 
     // Extract the last user message
-    let user_content = messages.iter()
-        .filter(|m| m.role == Role::User)
-        .last()
+    let user_content = session_messages.iter()
+        .rfind(|m| m.role == Role::User)
         .map(|m| m.content.clone())
         .unwrap_or_default();
 
     println!("Debug - Extracted user content: '{}'", user_content);
 
-    // Call the reasoning model
-    println!("API: Starting thinking phase with {}...", config.reasoning_model);
-    let reasoning = match process_reasoner_call(&client, &config, &user_content).await {
-        Ok(result) => {
-            println!("API: Thinking phase completed successfully");
-            result
-        }
-        Err(e) => {
-            println!("API: Error in thinking phase: {}", e);
-            
-            // Create the error message
-            let error_message = format!("Error in thinking phase: {}", e);
-            let error_json = json!({
-                "error": {
-                    "message": error_message,
-                    "type": "api_error"
-                }
-            }).to_string();
-            
-            // Send the error message
-            let formatted_message = format!("data: {}\n\n", error_json);
-            let _ = tx.send(formatted_message).await;
-            return;
-        }
-    };
-
-    // Check if this is a coding request
-    let is_coding = is_coding_request(&user_content);
-
-    // Process with crafting model for final response
-    if is_coding {
-        println!("API: Starting execution phase with {}...", config.craft_model);
-    } else {
-        println!("API: Starting response phase with {}...", config.craft_model);
-    }
-
     // Create a unique ID for this completion
-    let completion_id = format!("chatcmpl-{}", Uuid::new_v4().simple().to_string());
+    let completion_id = format!("chatcmpl-{}", Uuid::new_v4().simple());
     let created_timestamp = chrono::Utc::now().timestamp();
 
-    // Create a vector to hold all messages
-    let mut api_messages = vec![
-        json!({
-            "role": "system",
-            "content": if is_coding {
-                format!("You are a coding assistant. Use the following reasoning to help implement a solution: {}", reasoning)
-            } else {
-                format!("You are a helpful assistant. Use the following reasoning to help craft a response: {}", reasoning)
-            }
-        })
-    ];
-
-    // Add session messages
-    for message in &session_messages {
-        api_messages.push(json!({
-            "role": message.role.to_string().to_lowercase(),
-            "content": message.content
-        }));
+    // Replay a cached response instead of running the pipeline
+    if let CacheLookup::Hit(cached) = &cache_lookup {
+        println!("API: Serving cached response");
+        let mut chunks = vec![
+            aisettings::format_openai_role_chunk(&completion_id, created_timestamp as u64, &model_id),
+            aisettings::format_openai_chunk(&cached.answer, &completion_id, created_timestamp as u64, &model_id),
+        ];
+        if !cached.critiques.is_empty() {
+            chunks.push(aisettings::format_openai_critique_chunk(
+                &cached.critiques,
+                &completion_id,
+                created_timestamp as u64,
+                &model_id,
+            ));
+        }
+        for chunk in chunks {
+            let _ = tx.send(chunk).await;
+        }
+        record_turn(&state, &session_id, &session_messages, cached.answer.clone());
+        finish_stream(&tx, &completion_id, created_timestamp, &model_id).await;
+        return;
     }
 
-    // Send the initial role message
-    let initial_role_message = aisettings::format_openai_role_chunk(
-        &completion_id, 
-        created_timestamp as u64, 
-        &model
-    );
-    let _ = tx.send(initial_role_message).await;
-
-    // Prepare a simpler message format for OpenRouter
-    let simplified_messages = session_messages.iter().map(|msg| {
-        json!({
-            "role": msg.role.to_string(),
-            "content": msg.content
-        })
-    }).collect::<Vec<_>>();
-
-    // Send the request with simplified format
-    match client
-        .post(&format!("{}/v1/chat/completions", config.api_url))
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .header("HTTP-Referer", "https://app.dualmind.ai")
-        .header("X-Title", "DualMind API Client")
-        .json(&json!({
-            "model": config.craft_model,
-            "messages": simplified_messages,
-            "temperature": config.temperature,
-            "stream": true
-        }))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let status = response.status();
-            if !status.is_success() {
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                let error_message = format!("API request failed: {} - {}", status, error_text);
-                let error_json = json!({
-                    "error": {
-                        "message": error_message,
-                        "type": "api_error"
-                    }
-                }).to_string();
-                let formatted_message = format!("data: {}\n\n", error_json);
-                
-                // No error value to drop here, but let's be consistent
-                let _ = tx.send(formatted_message).await;
-                return;
-            }
-
-            let mut stream = response.bytes_stream();
-            let mut accumulated_response = String::new();
-
-            // Process the stream
-            while let Some(chunk_result) = stream.next().await {
-                match chunk_result {
-                    Ok(chunk) => {
-                        let chunk_str = String::from_utf8_lossy(&chunk);
-                        for line in chunk_str.lines() {
-                            if let Some(content) = process_stream(line) {
-                                accumulated_response.push_str(&content);
-                                
-                                // Format the content as an OpenAI-compatible chunk
-                                let formatted_chunk = aisettings::format_openai_chunk(
-                                    &content, 
-                                    &completion_id, 
-                                    created_timestamp as u64, 
-                                    &model
-                                );
-                                
-                                // Send the formatted chunk
-                                let _ = tx.send(formatted_chunk).await;
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        let error_message = format!("Stream error: {}", e);
-                        let error_json = json!({
-                            "error": {
-                                "message": error_message,
-                                "type": "api_error"
-                            }
-                        }).to_string();
-                        let formatted_message = format!("data: {}\n\n", error_json);
-                        
-                        // Drop the error value before the await
-                        drop(e);
-                        
-                        let _ = tx.send(formatted_message).await;
-                        return;
+    // Forward the final stage to the client and log the earlier ones
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let final_index = config.pipeline_stages().len() - 1;
+    // With a critique pass the answer may still change, so only send the final version
+    let stream_live = config.critique.is_none();
+    let forward_events = async {
+        let mut role_sent = false;
+        while let Some(event) = events_rx.recv().await {
+            match event {
+                PipelineEvent::TaskClassified { category } => {
+                    println!("API: Classified request as {}", category);
+                }
+                PipelineEvent::ReasoningDecided { mode, reason } => {
+                    println!("API: Using {} reasoning ({})", mode, reason);
+                }
+                PipelineEvent::StageStarted { index, name, model } => {
+                    println!("API: Starting {} stage with {}...", name, model);
+                    if index == final_index && !role_sent {
+                        role_sent = true;
+                        // Send the initial role message
+                        let initial_role_message = aisettings::format_openai_role_chunk(
+                            &completion_id,
+                            created_timestamp as u64,
+                            &model_id,
+                        );
+                        let _ = tx.send(initial_role_message).await;
                     }
                 }
+                PipelineEvent::Content { index, content } if index == final_index && stream_live => {
+                    // Format the content as an OpenAI-compatible chunk
+                    let formatted_chunk = aisettings::format_openai_chunk(
+                        &content,
+                        &completion_id,
+                        created_timestamp as u64,
+                        &model_id,
+                    );
+                    let _ = tx.send(formatted_chunk).await;
+                }
+                PipelineEvent::Content { content, .. } => print!("{}", content),
+                PipelineEvent::StageFinished { .. } => println!(),
+                PipelineEvent::StageCutOff { index } => {
+                    println!("\nAPI: Stage {} reached its reasoning budget and was cut off", index);
+                }
+                PipelineEvent::EnsembleMemberFinished { model, error, .. } => match error {
+                    None => println!("API: Ensemble model {} finished", model),
+                    Some(e) => println!("API: Ensemble model {} failed: {}", model, e),
+                },
+                PipelineEvent::CritiqueStarted { round, model } => {
+                    println!("API: Starting critique round {} with {}...", round, model);
+                }
+                PipelineEvent::CritiqueFinished { critique, .. } => {
+                    println!("API: Critique round {} approved: {}", critique.round, critique.approved);
+                }
             }
+        }
+    };
+    let previous_reasoning = session_reasoning(&state, &session_id);
+    let (result, ()) = tokio::join!(
+        run_pipeline(client, config, &session_messages, &previous_reasoning, Some(events_tx)),
+        forward_events
+    );
 
-            // If we didn't get any content from the model, send a fallback response
-            if accumulated_response.is_empty() {
-                // Create a fallback response
-                let fallback_content = "Here's a simple Rust Hello World program:\n\n```rust\nfn main() {\n    println!(\"Hello, world!\");\n}\n```\n\nTo run this program:\n\n1. Save it as `hello.rs`\n2. Compile it with `rustc hello.rs`\n3. Run the executable with `./hello`";
-                
-                // Format and send the fallback content
-                let formatted_chunk = aisettings::format_openai_chunk(
-                    fallback_content,
+    let mut accumulated_response = match result {
+        Ok(output) => {
+            store_in_cache(&state, &cache_lookup, &output);
+            set_session_reasoning(&state, &session_id, &output);
+            if !stream_live {
+                let answer_chunk = aisettings::format_openai_chunk(
+                    output.answer(),
                     &completion_id,
                     created_timestamp as u64,
-                    &model
+                    &model_id,
                 );
-                
-                // Send the formatted chunk
-                let _ = tx.send(formatted_chunk).await;
-                
-                // Update the accumulated response
-                accumulated_response = fallback_content.to_string();
-            }
-
-            // Add assistant response to session history
-            {
-                let mut sessions_lock = sessions.lock().unwrap();
-                if let Some(session) = sessions_lock.get_mut(&session_id) {
-                    session.messages.push(Message {
-                        role: Role::Assistant,
-                        content: accumulated_response,
-                    });
+                let _ = tx.send(answer_chunk).await;
+                if !output.critiques.is_empty() {
+                    let critique_chunk = aisettings::format_openai_critique_chunk(
+                        &output.critiques,
+                        &completion_id,
+                        created_timestamp as u64,
+                        &model_id,
+                    );
+                    let _ = tx.send(critique_chunk).await;
                 }
             }
-
-            // Send the final finish message
-            let finish_message = aisettings::format_openai_finish_chunk(
-                &completion_id, 
-                created_timestamp as u64, 
-                &model
-            );
-            let _ = tx.send(finish_message).await;
-
-            // Send the [DONE] message
-            let done_message = aisettings::format_done_message();
-            let _ = tx.send(done_message).await;
-        },
+            output.answer().to_string()
+        }
         Err(e) => {
-            let error_message = format!("Failed to send request: {}", e);
-            let error_json = json!({
-                "error": {
-                    "message": error_message,
-                    "type": "api_error"
-                }
-            }).to_string();
-            let formatted_message = format!("data: {}\n\n", error_json);
-            
-            // Drop the error value before the await
-            drop(e);
-            
+            println!("API: {}", e);
+            let formatted_message = format!("data: {}\n\n", json!(e.to_response()));
             let _ = tx.send(formatted_message).await;
+            return;
         }
+    };
+
+    // If we didn't get any content from the model, send a fallback response
+    if accumulated_response.is_empty() {
+        // Create a fallback response
+        let fallback_content = "Here's a simple Rust Hello World program:\n\n```rust\nfn main() {\n    println!(\"Hello, world!\");\n}\n```\n\nTo run this program:\n\n1. Save it as `hello.rs`\n2. Compile it with `rustc hello.rs`\n3. Run the executable with `./hello`";
+
+        // Format and send the fallback content
+        let formatted_chunk = aisettings::format_openai_chunk(
+            fallback_content,
+            &completion_id,
+            created_timestamp as u64,
+            &model_id,
+        );
+
+        // Send the formatted chunk
+        let _ = tx.send(formatted_chunk).await;
+
+        // Update the accumulated response
+        accumulated_response = fallback_content.to_string();
+    }
+
+    record_turn(&state, &session_id, &session_messages, accumulated_response);
+    finish_stream(&tx, &completion_id, created_timestamp, &model_id).await;
+}
+
+/// Close the stream
+async fn finish_stream(tx: &mpsc::Sender<String>, completion_id: &str, created_timestamp: i64, model_id: &str) {
+    // Send the final finish message
+    let finish_message = aisettings::format_openai_finish_chunk(
+        completion_id,
+        created_timestamp as u64,
+        model_id,
+    );
+    let _ = tx.send(finish_message).await;
+
+    // Send the [DONE] message
+    let done_message = aisettings::format_done_message();
+    let _ = tx.send(done_message).await;
+}
+
+/// Get or create a session and return its history with `messages` appended
+///
+/// The session itself is only updated by `record_turn`, once the request has
+/// been answered, so a failed request leaves no unanswered turn behind.
+fn session_context(state: &AppState, session_id: &str, messages: &[Message]) -> Vec<Message> {
+    cleanup_sessions_if_due(state);
+
+    let mut sessions = state.sessions.lock().unwrap();
+    let session = sessions
+        .entry(session_id.to_string())
+        .or_insert_with(|| crate::api::server::ChatSession {
+            messages: Vec::new(),
+            last_active: Instant::now(),
+            reasoning: Vec::new(),
+        });
+    session.last_active = Instant::now();
+    let mut context = session.messages.clone();
+    context.extend(messages.iter().cloned());
+    context
+}
+
+/// Save an answered turn: the context it was run on followed by the answer
+fn record_turn(state: &AppState, session_id: &str, session_messages: &[Message], answer: String) {
+    let mut sessions = state.sessions.lock().unwrap();
+    if let Some(session) = sessions.get_mut(session_id) {
+        session.messages = session_messages.to_vec();
+        session.messages.push(Message {
+            role: Role::Assistant,
+            content: answer,
+        });
+    }
+}
+
+/// Drop expired sessions, at most once a minute
+pub(crate) fn cleanup_sessions_if_due(state: &AppState) {
+    let mut last_cleanup = state.last_cleanup.lock().unwrap();
+    let now = Instant::now();
+    if now.duration_since(*last_cleanup) > std::time::Duration::from_secs(60) {
+        let mut sessions = state.sessions.lock().unwrap();
+        cleanup_old_sessions(&mut sessions);
+        *last_cleanup = now;
+    }
+}
+
+/// Reasoning stages of the session's previous turn
+fn session_reasoning(state: &AppState, session_id: &str) -> Vec<StageOutput> {
+    let sessions = state.sessions.lock().unwrap();
+    sessions
+        .get(session_id)
+        .map(|session| session.reasoning.clone())
+        .unwrap_or_default()
+}
+
+/// Remember a run's reasoning stages for the session's next turn
+fn set_session_reasoning(state: &AppState, session_id: &str, output: &PipelineOutput) {
+    let mut sessions = state.sessions.lock().unwrap();
+    if let Some(session) = sessions.get_mut(session_id) {
+        session.reasoning = output.reasoning_stages().to_vec();
+    }
+}
+
+/// Look the request up in the response cache, if one is configured
+fn lookup_cache(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &ChatCompletionRequest,
+    config: &Config,
+    session_messages: &[Message],
+) -> CacheLookup {
+    let Some(cache) = &state.cache else {
+        return CacheLookup::Bypass;
+    };
+
+    // Clients can ask for a fresh answer with `Cache-Control: no-cache`
+    let no_cache = headers
+        .get("Cache-Control")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("no-cache") || value.contains("no-store"));
+
+    let lookup = cache.lookup(&request.model, session_messages, config, request.max_tokens, no_cache);
+    println!("API: Cache {}", lookup.status().as_str());
+    lookup
+}
+
+/// Store a pipeline run under the key of a missed cache lookup
+fn store_in_cache(state: &AppState, lookup: &CacheLookup, output: &PipelineOutput) {
+    if let (Some(cache), CacheLookup::Miss(key)) = (&state.cache, lookup) {
+        cache.store(
+            key,
+            CachedResponse {
+                reasoning: output.reasoning(),
+                answer: output.answer().to_string(),
+                critiques: output.critiques.clone(),
+            },
+        );
     }
 }
 
 /// Build an error response
-pub fn build_error_response(
-    status: StatusCode,
-    message: &str,
-    error_type: &str,
-) -> axum::response::Response<Body> {
-    let error_json = serde_json::json!({
-        "error": {
-            "message": message,
-            "type": error_type,
-            "param": null,
-            "code": status.as_u16()
-        }
-    });
+pub fn build_error_response(error: &Error) -> axum::response::Response<Body> {
+    let error_json = error.to_response();
 
     axum::response::Response::builder()
-        .status(status)
+        .status(error.kind().status())
         .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
         .body(Body::from(
@@ -556,6 +524,29 @@ pub fn build_error_response(
         .unwrap()
 }
 
+/// Build the 404 returned for an unknown model id
+pub(crate) fn model_not_found_response(model: &str) -> axum::response::Response<Body> {
+    build_error_response(&Error::new(
+        ErrorKind::ModelNotFound,
+        format!("The model `{}` does not exist", model),
+    ))
+}
+
+/// Expose server metrics in the Prometheus text format
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let body = state
+        .cache
+        .as_ref()
+        .map(|cache| cache.prometheus_metrics())
+        .unwrap_or_default();
+
+    axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap()
+}
+
 /// Clear a session
 pub async fn clear_session(
     State(state): State<Arc<AppState>>,
@@ -575,69 +566,40 @@ pub async fn clear_session(
     }
 }
 
-/// List available models
-pub async fn list_models() -> impl IntoResponse {
-    let response = serde_json::json!({
-        "object": "list",
-        "data": [
-            {
-                "id": "r1sonnet",
-                "object": "model",
-                "created": 1677610602,
-                "owned_by": "organization-owner",
-                "permission": [
-                    {
-                        "id": "modelperm-123",
-                        "object": "model_permission",
-                        "created": 1677610602,
-                        "allow_create_engine": false,
-                        "allow_sampling": true,
-                        "allow_logprobs": true,
-                        "allow_search_indices": false,
-                        "allow_view": true,
-                        "allow_fine_tuning": false,
-                        "organization": "*",
-                        "group": null,
-                        "is_blocking": false
-                    }
-                ],
-                "root": "r1sonnet",
-                "parent": null
-            }
-        ]
-    });
+/// List the configured pipelines as models
+pub async fn list_models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let models: Vec<_> = state.config.pipelines.iter().map(model_json).collect();
 
-    (StatusCode::OK, Json(response))
+    (StatusCode::OK, Json(json!({
+        "object": "list",
+        "data": models
+    })))
 }
 
 /// Get model details
-pub async fn get_model(axum::extract::Path(model): axum::extract::Path<String>) -> impl IntoResponse {
-    let response = serde_json::json!({
-        "id": model,
+pub async fn get_model(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(model): axum::extract::Path<String>,
+) -> axum::response::Response<Body> {
+    match state.config.find_pipeline(&model) {
+        Some(pipeline) => (StatusCode::OK, Json(model_json(pipeline))).into_response(),
+        None => model_not_found_response(&model),
+    }
+}
+
+/// Describe a pipeline in the OpenAI model object format
+fn model_json(pipeline: &Pipeline) -> serde_json::Value {
+    json!({
+        "id": pipeline.id,
         "object": "model",
         "created": 1677610602,
-        "owned_by": "organization-owner",
-        "permission": [
-            {
-                "id": "modelperm-123",
-                "object": "model_permission",
-                "created": 1677610602,
-                "allow_create_engine": false,
-                "allow_sampling": true,
-                "allow_logprobs": true,
-                "allow_search_indices": false,
-                "allow_view": true,
-                "allow_fine_tuning": false,
-                "organization": "*",
-                "group": null,
-                "is_blocking": false
-            }
-        ],
-        "root": model,
-        "parent": null
-    });
-
-    (StatusCode::OK, Json(response))
+        "owned_by": "dualmind",
+        "root": pipeline.id,
+        "parent": null,
+        "description": pipeline.description,
+        "reasoning_model": pipeline.reasoning_model,
+        "craft_model": pipeline.craft_model
+    })
 }
 
 /// CORS options handler
@@ -1,8 +1,10 @@
 //! API-related functionality
 
+pub mod cache;
 pub mod client;
 pub mod handlers;
 pub mod models;
+pub mod responses;
 pub mod server;
 
 // Re-export commonly used items
@@ -1,6 +1,7 @@
 //! API data models
 
 use serde::{Deserialize, Serialize};
+use crate::core::adaptive::{ReasoningBudget, ReasoningEffort, ReasoningMode};
 use crate::models::Message;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,10 +14,54 @@ pub struct ChatCompletionRequest {
     pub temperature: Option<f32>,
     #[serde(default)]
     pub max_tokens: Option<i32>,
+    /// Overrides the adaptive reasoning policy for this request
+    #[serde(default)]
+    pub reasoning_mode: Option<ReasoningMode>,
+    #[serde(default)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Reasoning settings in the `reasoning: { effort, max_tokens }` form
+    #[serde(default)]
+    pub reasoning: Option<ReasoningParams>,
     #[serde(skip)]
     pub session_id: Option<String>, // This is now hidden from the public API
 }
 
+/// The `reasoning` object of a chat completion request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReasoningParams {
+    #[serde(default)]
+    pub effort: Option<ReasoningEffort>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Time limit for the reasoning stages (a DualMind extension)
+    #[serde(default)]
+    pub max_seconds: Option<u64>,
+}
+
+impl ReasoningParams {
+    /// Override the configured reasoning budget with these settings
+    pub fn apply(&self, budget: &mut ReasoningBudget) {
+        if let Some(effort) = self.effort {
+            budget.effort = Some(effort);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            budget.max_tokens = Some(max_tokens);
+        }
+        if let Some(max_seconds) = self.max_seconds {
+            budget.max_seconds = Some(max_seconds);
+        }
+    }
+}
+
+impl ChatCompletionRequest {
+    /// Override the configured reasoning budget with the request's settings
+    pub fn apply_reasoning_budget(&self, budget: &mut ReasoningBudget) {
+        let mut reasoning = self.reasoning.clone().unwrap_or_default();
+        reasoning.effort = self.reasoning_effort.or(reasoning.effort);
+        reasoning.apply(budget);
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -0,0 +1,547 @@
+//! OpenAI Responses API (`POST /v1/responses`)
+//!
+//! Runs the same pipeline as chat completions. The input items become the
+//! conversation, `instructions` a system message for this turn only, and the
+//! reasoning stages are reported as a reasoning item with one summary part per
+//! stage. Responses are kept in the session store under their id, so
+//! `previous_response_id` can continue from them for as long as a session
+//! would be kept.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::api::handlers::{build_error_response, cleanup_sessions_if_due, model_not_found_response};
+use crate::api::models::ReasoningParams;
+use crate::api::server::{AppState, ChatSession};
+use crate::config::Config;
+use crate::core::adaptive::ReasoningMode;
+use crate::core::llm::estimate_tokens;
+use crate::core::pipeline::{run_pipeline, PipelineEvent, PipelineOutput, StageOutput};
+use crate::error::{Error, ErrorKind};
+use crate::models::{Message, Role};
+
+/// Body of `POST /v1/responses`
+#[derive(Debug, Deserialize)]
+pub struct CreateResponseRequest {
+    pub model: String,
+    /// A string, or a list of input items
+    #[serde(default)]
+    pub input: Value,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub previous_response_id: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub reasoning: Option<ReasoningParams>,
+    /// Overrides the adaptive reasoning policy for this request
+    #[serde(default)]
+    pub reasoning_mode: Option<ReasoningMode>,
+    /// Keep the response so later requests can chain onto it
+    #[serde(default = "default_store")]
+    pub store: bool,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+fn default_store() -> bool {
+    true
+}
+
+/// A response being created, with the request fields it echoes back
+struct Turn {
+    id: String,
+    created_at: i64,
+    model: String,
+    instructions: Option<String>,
+    previous_response_id: Option<String>,
+    temperature: f32,
+    max_output_tokens: Option<u32>,
+    store: bool,
+    metadata: Value,
+    /// Everything sent to the pipeline, including the instructions
+    messages: Vec<Message>,
+    /// The conversation to store with the answer, without the instructions
+    context: Vec<Message>,
+    reasoning_id: String,
+    message_id: String,
+}
+
+/// Handle the Responses API endpoint
+pub async fn create_response(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateResponseRequest>,
+) -> impl IntoResponse {
+    println!("Received Responses API request for {}", request.model);
+
+    let mut config = match state.config.find_pipeline(&request.model) {
+        Some(pipeline) => state.config.for_pipeline(pipeline),
+        None => return model_not_found_response(&request.model),
+    };
+    if let Some(mode) = request.reasoning_mode {
+        config.reasoning_mode = mode;
+    }
+    if let Some(reasoning) = &request.reasoning {
+        reasoning.apply(&mut config.reasoning_budget);
+    }
+    if let Some(temperature) = request.temperature {
+        config.temperature = temperature;
+    }
+
+    let input = match input_messages(&request.input) {
+        Ok(input) => input,
+        Err(e) => return build_error_response(&e),
+    };
+    let (mut context, previous_reasoning) = match previous_turn(&state, request.previous_response_id.as_deref()) {
+        Ok(previous) => previous,
+        Err(e) => return build_error_response(&e),
+    };
+    context.extend(input);
+
+    let mut messages = Vec::new();
+    if let Some(instructions) = &request.instructions {
+        messages.push(Message {
+            role: Role::System,
+            content: instructions.clone(),
+        });
+    }
+    messages.extend(context.iter().cloned());
+
+    let turn = Turn {
+        id: format!("resp_{}", Uuid::new_v4().simple()),
+        created_at: chrono::Utc::now().timestamp(),
+        model: request.model,
+        instructions: request.instructions,
+        previous_response_id: request.previous_response_id,
+        temperature: config.temperature,
+        max_output_tokens: request.max_output_tokens,
+        store: request.store,
+        metadata: request.metadata.unwrap_or_else(|| json!({})),
+        messages,
+        context,
+        reasoning_id: format!("rs_{}", Uuid::new_v4().simple()),
+        message_id: format!("msg_{}", Uuid::new_v4().simple()),
+    };
+
+    if request.stream {
+        let (tx, rx) = mpsc::channel(100);
+        tokio::task::spawn(stream_turn(state, turn, config, previous_reasoning, tx));
+        let body = Body::from_stream(ReceiverStream::new(rx).map(Ok::<_, std::convert::Infallible>));
+        return axum::response::Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(body)
+            .unwrap();
+    }
+
+    println!("API: Starting pipeline with {} stages...", config.pipeline_stages().len());
+    let output = match run_pipeline(&state.client, &config, &turn.messages, &previous_reasoning, None).await {
+        Ok(output) => output,
+        Err(e) => {
+            println!("API: {}", e);
+            return build_error_response(&e);
+        }
+    };
+    store_turn(&state, &turn, &output);
+
+    axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::from(completed_response(&turn, &output).to_string()))
+        .unwrap()
+}
+
+/// Convert the `input` of a request into messages
+fn input_messages(input: &Value) -> Result<Vec<Message>, Error> {
+    let items = match input {
+        Value::String(text) => {
+            return Ok(vec![Message {
+                role: Role::User,
+                content: text.clone(),
+            }])
+        }
+        Value::Array(items) => items,
+        Value::Null => return Err(invalid_request("`input` is required")),
+        _ => return Err(invalid_request("`input` must be a string or a list of input items")),
+    };
+
+    let mut messages = Vec::new();
+    for item in items {
+        match item["type"].as_str().unwrap_or("message") {
+            "message" => messages.push(Message {
+                role: match item["role"].as_str() {
+                    Some("user") => Role::User,
+                    Some("assistant") => Role::Assistant,
+                    Some("system" | "developer") => Role::System,
+                    other => {
+                        return Err(invalid_request(format!("Unsupported message role {:?}", other.unwrap_or_default())))
+                    }
+                },
+                content: item_text(&item["content"])?,
+            }),
+            // Reasoning sent back by clients that keep the conversation themselves
+            "reasoning" => {}
+            other => return Err(invalid_request(format!("Unsupported input item type `{}`", other))),
+        }
+    }
+    if messages.is_empty() {
+        return Err(invalid_request("`input` has no messages"));
+    }
+    Ok(messages)
+}
+
+/// The text of a message's content, a string or a list of text parts
+fn item_text(content: &Value) -> Result<String, Error> {
+    if let Some(text) = content.as_str() {
+        return Ok(text.to_string());
+    }
+    let Some(parts) = content.as_array() else {
+        return Err(invalid_request("Message content must be a string or a list of content parts"));
+    };
+    let mut texts = Vec::new();
+    for part in parts {
+        match (part["type"].as_str(), part["text"].as_str()) {
+            (Some("input_text" | "output_text" | "text"), Some(text)) => texts.push(text),
+            (kind, _) => {
+                return Err(invalid_request(format!(
+                    "Unsupported content part type `{}`",
+                    kind.unwrap_or("none")
+                )))
+            }
+        }
+    }
+    Ok(texts.join("\n"))
+}
+
+fn invalid_request(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidRequest, message)
+}
+
+/// The conversation and reasoning of the response `id` chains onto
+fn previous_turn(state: &AppState, id: Option<&str>) -> Result<(Vec<Message>, Vec<StageOutput>), Error> {
+    let Some(id) = id else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    cleanup_sessions_if_due(state);
+    let mut sessions = state.sessions.lock().unwrap();
+    let session = sessions
+        .get_mut(id)
+        .ok_or_else(|| invalid_request(format!("Previous response with id '{}' not found.", id)))?;
+    session.last_active = Instant::now();
+    Ok((session.messages.clone(), session.reasoning.clone()))
+}
+
+/// Keep the conversation up to this response so later requests can chain onto it
+fn store_turn(state: &AppState, turn: &Turn, output: &PipelineOutput) {
+    if !turn.store {
+        return;
+    }
+    let mut messages = turn.context.clone();
+    messages.push(Message {
+        role: Role::Assistant,
+        content: output.answer().to_string(),
+    });
+    state.sessions.lock().unwrap().insert(
+        turn.id.clone(),
+        ChatSession {
+            messages,
+            last_active: Instant::now(),
+            reasoning: output.reasoning_stages().to_vec(),
+        },
+    );
+}
+
+/// Reasoning summaries of the stages that ran this turn
+fn reasoning_summaries(output: &PipelineOutput) -> Vec<String> {
+    if output.reasoning_mode == ReasoningMode::Skip {
+        return Vec::new();
+    }
+    output.reasoning_stages().iter().map(|s| s.content.clone()).collect()
+}
+
+fn reasoning_item(turn: &Turn, summaries: &[String]) -> Value {
+    let summary: Vec<Value> = summaries.iter().map(|text| summary_part(text)).collect();
+    json!({ "type": "reasoning", "id": turn.reasoning_id, "summary": summary })
+}
+
+fn summary_part(text: &str) -> Value {
+    json!({ "type": "summary_text", "text": text })
+}
+
+fn message_item(turn: &Turn, text: Option<&str>) -> Value {
+    let (status, content) = match text {
+        Some(text) => ("completed", vec![text_part(text)]),
+        None => ("in_progress", Vec::new()),
+    };
+    json!({
+        "type": "message",
+        "id": turn.message_id,
+        "status": status,
+        "role": "assistant",
+        "content": content
+    })
+}
+
+fn text_part(text: &str) -> Value {
+    json!({ "type": "output_text", "text": text, "annotations": [] })
+}
+
+/// The response object in `status`, with `output` and `usage` once known
+fn response_object(turn: &Turn, status: &str, output: Vec<Value>, usage: Value, error: Value) -> Value {
+    json!({
+        "id": turn.id,
+        "object": "response",
+        "created_at": turn.created_at,
+        "status": status,
+        "error": error,
+        "incomplete_details": null,
+        "instructions": turn.instructions,
+        "max_output_tokens": turn.max_output_tokens,
+        "model": turn.model,
+        "output": output,
+        "parallel_tool_calls": false,
+        "previous_response_id": turn.previous_response_id,
+        "store": turn.store,
+        "temperature": turn.temperature,
+        "tool_choice": "none",
+        "tools": [],
+        "top_p": null,
+        "usage": usage,
+        "metadata": turn.metadata
+    })
+}
+
+fn completed_response(turn: &Turn, output: &PipelineOutput) -> Value {
+    let summaries = reasoning_summaries(output);
+    let mut items = Vec::new();
+    if !summaries.is_empty() {
+        items.push(reasoning_item(turn, &summaries));
+    }
+    items.push(message_item(turn, Some(output.answer())));
+
+    // Estimated like batch results: about four characters per token
+    let input_tokens: usize = turn.messages.iter().map(|m| estimate_tokens(&m.content)).sum();
+    let reasoning_tokens: usize = summaries.iter().map(|s| estimate_tokens(s)).sum();
+    let output_tokens = reasoning_tokens + estimate_tokens(output.answer());
+    let usage = json!({
+        "input_tokens": input_tokens,
+        "input_tokens_details": { "cached_tokens": 0 },
+        "output_tokens": output_tokens,
+        "output_tokens_details": { "reasoning_tokens": reasoning_tokens },
+        "total_tokens": input_tokens + output_tokens
+    });
+    response_object(turn, "completed", items, usage, Value::Null)
+}
+
+/// Sends typed `response.*` server-sent events with increasing sequence numbers
+struct EventWriter {
+    tx: mpsc::Sender<String>,
+    sequence: u64,
+}
+
+impl EventWriter {
+    async fn send(&mut self, kind: &str, mut data: Value) {
+        data["type"] = json!(kind);
+        data["sequence_number"] = json!(self.sequence);
+        self.sequence += 1;
+        let _ = self.tx.send(format!("event: {}\ndata: {}\n\n", kind, data)).await;
+    }
+}
+
+/// Where a streamed response has got to
+#[derive(Default)]
+struct StreamProgress {
+    /// Summary texts of the reasoning stages started so far
+    summaries: Vec<String>,
+    reasoning_done: bool,
+    message_started: bool,
+}
+
+impl StreamProgress {
+    fn message_index(&self) -> usize {
+        usize::from(!self.summaries.is_empty())
+    }
+}
+
+/// Run the pipeline, streaming reasoning summaries and the answer as events
+async fn stream_turn(
+    state: Arc<AppState>,
+    turn: Turn,
+    config: Config,
+    previous_reasoning: Vec<StageOutput>,
+    tx: mpsc::Sender<String>,
+) {
+    let mut events = EventWriter { tx, sequence: 0 };
+    let in_progress = response_object(&turn, "in_progress", Vec::new(), Value::Null, Value::Null);
+    events.send("response.created", json!({ "response": in_progress })).await;
+    events.send("response.in_progress", json!({ "response": in_progress })).await;
+
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let final_index = config.pipeline_stages().len() - 1;
+    // With a critique pass the answer may still change, so only send the final version
+    let stream_live = config.critique.is_none();
+    let mut progress = StreamProgress::default();
+    let forward_events = async {
+        while let Some(event) = events_rx.recv().await {
+            match event {
+                PipelineEvent::StageStarted { index, name, model } if index < final_index => {
+                    println!("API: Starting {} stage with {}...", name, model);
+                    if progress.summaries.is_empty() {
+                        let item = reasoning_item(&turn, &[]);
+                        events.send("response.output_item.added", json!({ "output_index": 0, "item": item })).await;
+                    }
+                    progress.summaries.push(String::new());
+                    let summary_index = progress.summaries.len() - 1;
+                    events
+                        .send(
+                            "response.reasoning_summary_part.added",
+                            json!({
+                                "item_id": turn.reasoning_id,
+                                "output_index": 0,
+                                "summary_index": summary_index,
+                                "part": summary_part("")
+                            }),
+                        )
+                        .await;
+                }
+                PipelineEvent::Content { index, content } if index < final_index => {
+                    if let Some(summary) = progress.summaries.last_mut() {
+                        summary.push_str(&content);
+                        let summary_index = progress.summaries.len() - 1;
+                        events
+                            .send(
+                                "response.reasoning_summary_text.delta",
+                                json!({
+                                    "item_id": turn.reasoning_id,
+                                    "output_index": 0,
+                                    "summary_index": summary_index,
+                                    "delta": content
+                                }),
+                            )
+                            .await;
+                    }
+                }
+                PipelineEvent::StageFinished { index } if index < final_index => {
+                    let summary_index = progress.summaries.len().saturating_sub(1);
+                    let text = progress.summaries.last().cloned().unwrap_or_default();
+                    let ids = json!({ "item_id": turn.reasoning_id, "output_index": 0, "summary_index": summary_index });
+                    let mut done = ids.clone();
+                    done["text"] = json!(text);
+                    events.send("response.reasoning_summary_text.done", done).await;
+                    let mut done = ids;
+                    done["part"] = summary_part(&text);
+                    events.send("response.reasoning_summary_part.done", done).await;
+                }
+                PipelineEvent::StageStarted { index, name, model } => {
+                    println!("API: Starting {} stage with {}...", name, model);
+                    if index == final_index && !progress.message_started {
+                        start_message(&mut events, &turn, &mut progress).await;
+                    }
+                }
+                PipelineEvent::Content { index, content } if index == final_index && stream_live => {
+                    events.send("response.output_text.delta", text_event(&turn, &progress, "delta", json!(content))).await;
+                }
+                PipelineEvent::StageCutOff { index } => {
+                    println!("API: Stage {} reached its reasoning budget and was cut off", index);
+                }
+                PipelineEvent::EnsembleMemberFinished { model, error: Some(e), .. } => {
+                    println!("API: Ensemble model {} failed: {}", model, e);
+                }
+                _ => {}
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(
+        run_pipeline(&state.client, &config, &turn.messages, &previous_reasoning, Some(events_tx)),
+        forward_events
+    );
+
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => {
+            println!("API: {}", e);
+            let error = json!({
+                "code": e.code().unwrap_or_else(|| "server_error".to_string()),
+                "message": e.to_string()
+            });
+            let failed = response_object(&turn, "failed", Vec::new(), Value::Null, error);
+            events.send("response.failed", json!({ "response": failed })).await;
+            return;
+        }
+    };
+
+    if !progress.message_started {
+        start_message(&mut events, &turn, &mut progress).await;
+    }
+    let answer = output.answer();
+    if !stream_live {
+        events.send("response.output_text.delta", text_event(&turn, &progress, "delta", json!(answer))).await;
+    }
+    events.send("response.output_text.done", text_event(&turn, &progress, "text", json!(answer))).await;
+    events
+        .send("response.content_part.done", text_event(&turn, &progress, "part", text_part(answer)))
+        .await;
+    events
+        .send(
+            "response.output_item.done",
+            json!({ "output_index": progress.message_index(), "item": message_item(&turn, Some(answer)) }),
+        )
+        .await;
+
+    store_turn(&state, &turn, &output);
+    events.send("response.completed", json!({ "response": completed_response(&turn, &output) })).await;
+}
+
+/// Close the reasoning item, if any, and open the answer message
+async fn start_message(events: &mut EventWriter, turn: &Turn, progress: &mut StreamProgress) {
+    if !progress.summaries.is_empty() && !progress.reasoning_done {
+        progress.reasoning_done = true;
+        let item = reasoning_item(turn, &progress.summaries);
+        events.send("response.output_item.done", json!({ "output_index": 0, "item": item })).await;
+    }
+    progress.message_started = true;
+    let output_index = progress.message_index();
+    events
+        .send(
+            "response.output_item.added",
+            json!({ "output_index": output_index, "item": message_item(turn, None) }),
+        )
+        .await;
+    events
+        .send("response.content_part.added", text_event(turn, progress, "part", text_part("")))
+        .await;
+}
+
+/// An event about the answer's text part, with `field` set to `value`
+fn text_event(turn: &Turn, progress: &StreamProgress, field: &str, value: Value) -> Value {
+    let mut event = json!({
+        "item_id": turn.message_id,
+        "output_index": progress.message_index(),
+        "content_index": 0
+    });
+    event[field] = value;
+    event
+}
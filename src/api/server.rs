@@ -3,29 +3,37 @@
 use axum::{
     Router,
     routing::{get, options, post},
-    Json,
-    response::IntoResponse,
 };
 use reqwest::Client;
-use serde_json::json;
 use std::{
     collections::HashMap,
     net::SocketAddr,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
+use axum_server::{Handle, tls_rustls::RustlsConfig};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
 use tokio::signal;
 
 use crate::api::handlers::{
-    chat_completions, clear_session, get_model, options_handler
+    chat_completions, clear_session, get_model, list_models, metrics, options_handler
 };
+use crate::api::cache::ResponseCache;
+use crate::api::responses::create_response;
 use crate::config::Config;
+use crate::core::pipeline::StageOutput;
 use crate::middleware;
 use crate::models::Message;
 
 pub struct ChatSession {
     pub messages: Vec<Message>,
     pub last_active: Instant,
+    /// Reasoning stages of the last turn, reused when reasoning is skipped
+    pub reasoning: Vec<StageOutput>,
 }
 
 pub struct AppState {
@@ -33,6 +41,26 @@ pub struct AppState {
     pub sessions: Arc<Mutex<HashMap<String, ChatSession>>>,
     pub last_cleanup: Arc<Mutex<Instant>>,
     pub config: Config,
+    /// Response cache, when enabled
+    pub cache: Option<ResponseCache>,
+}
+
+impl AppState {
+    /// Create the server state, opening the response cache if one is configured
+    pub fn new(client: Client, config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let cache = config.cache.clone().map(ResponseCache::new).transpose()?;
+        if let Some(cache) = &config.cache {
+            println!("Response cache enabled ({}, up to {} entries)", cache.backend.as_str(), cache.capacity);
+        }
+
+        Ok(Self {
+            client,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            last_cleanup: Arc::new(Mutex::new(Instant::now())),
+            config,
+            cache,
+        })
+    }
 }
 
 const SESSION_TIMEOUT: Duration = Duration::from_secs(60 * 30); // 30 minutes
@@ -42,44 +70,146 @@ pub async fn start(
     client: Client,
     config: Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let state = Arc::new(AppState {
-        client,
-        sessions: Arc::new(Mutex::new(HashMap::new())),
-        last_cleanup: Arc::new(Mutex::new(Instant::now())),
-        config,
-    });
+    let host = config.host.clone();
+    let port = config.port;
+    let unix_socket = config.unix_socket.clone();
+    let tls = match (config.tls_cert.clone(), config.tls_key.clone()) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => return Err("Both --tls_cert and --tls_key must be set to enable TLS".into()),
+    };
+
+    let state = Arc::new(AppState::new(client, config)?);
+    let app = router(state);
+
+    // Run it on a Unix socket if requested, otherwise on TCP (optionally with TLS)
+    if let Some(path) = unix_socket {
+        if tls.is_some() {
+            return Err("TLS is not supported when listening on a Unix socket".into());
+        }
+        return serve_unix(&path, app).await;
+    }
+
+    let addr: SocketAddr = tokio::net::lookup_host((host.as_str(), port))
+        .await?
+        .next()
+        .ok_or_else(|| format!("Could not resolve host: {}", host))?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    println!("🚀 API Server running on {scheme}://{addr}");
+    print_example_curl(&format!("{scheme}://{addr}"));
+
+    if let Some((cert, key)) = tls {
+        return serve_tls(addr, &cert, &key, app).await;
+    }
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
 
-    // Build our application with routes
-    let app = Router::new()
+    Ok(())
+}
+
+/// Build the API routes around `state`
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
         .route(
             "/",
             get(|| async { "DualMind API Server - OpenAI Compatible" }),
         )
         .route("/v1/chat/completions", post(chat_completions))
         .route("/v1/chat/completions", options(options_handler))
+        .route("/v1/responses", post(create_response))
+        .route("/v1/responses", options(options_handler))
         .route("/v1/models", get(list_models))
         .route("/v1/models/:model", get(get_model))
         .route("/v1/sessions/:session_id/clear", post(clear_session))
+        .route("/metrics", get(metrics))
         .layer(axum::middleware::from_fn(middleware::log_request))
-        .with_state(state);
+        .with_state(state)
+}
+
+/// Serve the API over TLS using the given PEM certificate and key
+async fn serve_tls(
+    addr: SocketAddr,
+    cert: &str,
+    key: &str,
+    app: Router,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls_config = RustlsConfig::from_pem_file(cert, key).await?;
+
+    // axum-server has its own shutdown handle, so forward the signal to it
+    let handle = Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown_handle.graceful_shutdown(Some(Duration::from_secs(10)));
+    });
+
+    axum_server::bind_rustls(addr, tls_config)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+/// Serve the API on a Unix domain socket
+#[cfg(unix)]
+async fn serve_unix(path: &str, app: Router) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Remove a stale socket left behind by a previous run, but nothing else
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => return Err(format!("{} already exists and is not a socket", path).into()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    println!("🚀 API Server running on unix:{path}");
+    println!("Example curl:");
+    println!("curl --unix-socket {path} http://localhost/v1/models");
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (socket, _) = tokio::select! {
+            result = listener.accept() => result?,
+            _ = &mut shutdown => break,
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let io = TokioIo::new(socket);
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(io, service)
+                .await
+            {
+                eprintln!("Unix socket connection error: {}", e);
+            }
+        });
+    }
+
+    let _ = std::fs::remove_file(path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: &str, _app: Router) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Unix sockets are not supported on this platform".into())
+}
 
-    // Run it with hyper
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    println!("🚀 API Server running on {addr}");
+/// Print an example request against the running server
+fn print_example_curl(base_url: &str) {
     println!("Example curl:");
-    println!("curl http://localhost:3000/v1/chat/completions \\");
+    println!("curl {base_url}/v1/chat/completions \\");
     println!("  -H \"Content-Type: application/json\" \\");
     println!("  -H \"Authorization: Bearer YOUR_API_KEY\" \\");
     println!(
         "  -d '{{\"model\": \"dualmind\", \"messages\": [{{\"role\": \"user\", \"content\": \"Hello world\"}}], \"stream\": false}}'"
     );
-
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
-
-    Ok(())
 }
 
 /// Helper function to clean up old sessions
@@ -121,20 +251,3 @@ async fn shutdown_signal() {
 
     println!("Shutting down gracefully...");
 }
-
-/// List available models
-pub async fn list_models() -> impl IntoResponse {
-    let models = vec![
-        json!({
-            "id": "dualmind",
-            "object": "model",
-            "created": 1677610602,
-            "owned_by": "organization-owner"
-        })
-    ];
-    
-    Json(json!({
-        "object": "list",
-        "data": models
-    }))
-} 
\ No newline at end of file
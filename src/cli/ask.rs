@@ -0,0 +1,181 @@
+//! Non-interactive `ask` mode for scripting
+//!
+//! `dualmind ask "explain this" < file.rs` runs one pipeline turn over the
+//! question (plus anything piped on stdin) and prints only the answer, or a
+//! JSON report with `--json`. `--out-dir=<dir>` also writes the answer's
+//! code blocks to files.
+
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+use std::time::Instant;
+
+use reqwest::Client;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::cli::code;
+use crate::config::Config;
+use crate::core::llm::estimate_tokens;
+use crate::core::pipeline::{run_pipeline, PipelineEvent};
+use crate::models::{Message, Role};
+
+/// Run a single question and print the result
+pub async fn run(
+    client: Client,
+    mut config: Config,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(id) = config.pipeline.clone() {
+        let pipeline = config
+            .find_pipeline(&id)
+            .ok_or_else(|| format!("Unknown pipeline: {}", id))?;
+        config = config.for_pipeline(pipeline);
+    }
+
+    let json_output = args.iter().any(|a| a == "--json");
+    let out_dir = args.iter().find_map(|a| a.strip_prefix("--out-dir="));
+    let question = read_question(args)?;
+
+    let session_messages = vec![Message {
+        role: Role::User,
+        content: question,
+    }];
+
+    // Time each stage from the pipeline's events
+    let started = Instant::now();
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let collect_timings = async {
+        let mut stage_started = Vec::new();
+        let mut timings = Vec::new();
+        while let Some(event) = events_rx.recv().await {
+            match event {
+                PipelineEvent::StageStarted { index, .. } => stage_started.push((index, Instant::now())),
+                PipelineEvent::StageFinished { index } => {
+                    if let Some((_, start)) = stage_started.iter().rev().find(|(i, _)| *i == index) {
+                        timings.push((index, start.elapsed().as_millis()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        timings
+    };
+    let (result, timings) = tokio::join!(
+        run_pipeline(&client, &config, &session_messages, &[], Some(events_tx)),
+        collect_timings
+    );
+    let output = result.map_err(|e| e.to_string())?;
+    let total_ms = started.elapsed().as_millis();
+
+    let written = match out_dir {
+        Some(dir) => write_code_blocks(Path::new(dir), output.answer())?,
+        None => Vec::new(),
+    };
+
+    if !json_output {
+        println!("{}", output.answer().trim_end());
+        return Ok(());
+    }
+
+    let reasoning: Vec<&str> = output
+        .reasoning_stages()
+        .iter()
+        .map(|s| s.content.as_str())
+        .collect();
+    let stages: Vec<_> = output
+        .stages
+        .iter()
+        .enumerate()
+        .map(|(index, stage)| {
+            // A refined final stage runs more than once; report its total time
+            let duration_ms: u128 = timings.iter().filter(|(i, _)| *i == index).map(|(_, ms)| ms).sum();
+            json!({
+                "name": stage.name,
+                "model": stage.model,
+                "duration_ms": duration_ms,
+            })
+        })
+        .collect();
+    let prompt_tokens = estimate_tokens(&session_messages[0].content);
+    let completion_tokens: usize = output.stages.iter().map(|s| estimate_tokens(&s.content)).sum();
+
+    let mut report = json!({
+        "reasoning": reasoning.join("\n\n"),
+        "answer": output.answer(),
+        "reasoning_mode": output.reasoning_mode,
+        "models": stages.iter().map(|s| s["model"].clone()).collect::<Vec<_>>(),
+        "timings": {
+            "stages": stages,
+            "total_ms": total_ms,
+        },
+        // Streams carry no usage, so these are estimated at four characters per token
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+            "estimated": true,
+        },
+    });
+    if !output.critiques.is_empty() {
+        report["critique"] = json!(output.critiques);
+    }
+    if out_dir.is_some() {
+        report["files"] = json!(written);
+    }
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Write every code block in `answer` under `dir`, returning the paths written
+///
+/// Blocks are named from the answer's file name hints, falling back to
+/// `block-<n>.<ext>`; hints that would leave `dir` are refused.
+fn write_code_blocks(dir: &Path, answer: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut written: Vec<String> = Vec::new();
+    for (i, block) in code::extract(answer).iter().enumerate() {
+        let default_name = block.default_filename(i + 1);
+        let name = block.filename.clone().unwrap_or_else(|| default_name.clone());
+        let path = match code::path_within(dir, &name) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("{}, saving as {}", e, default_name);
+                dir.join(&default_name)
+            }
+        };
+        // Two blocks for the same file: keep the first, name the second by number
+        let path = if written.contains(&path.to_string_lossy().into_owned()) {
+            dir.join(&default_name)
+        } else {
+            path
+        };
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &block.content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        eprintln!("Wrote {}", path.display());
+        written.push(path.to_string_lossy().into_owned());
+    }
+    Ok(written)
+}
+
+/// Build the question from the positional argument and any piped stdin
+fn read_question(args: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+    let prompt = args.iter().find(|a| !a.starts_with("--")).cloned().unwrap_or_default();
+
+    let mut input = String::new();
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        stdin.lock().read_to_string(&mut input)?;
+    }
+
+    let question = match (prompt.trim().is_empty(), input.trim().is_empty()) {
+        (true, true) => return Err("Usage: dualmind ask \"<question>\" [--json] [< file]".into()),
+        (false, true) => prompt,
+        (true, false) => input,
+        (false, false) => format!("{}\n\n{}", prompt.trim(), input.trim_end()),
+    };
+    Ok(question)
+}
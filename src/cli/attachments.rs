@@ -0,0 +1,257 @@
+//! Local files attached to terminal prompts with `/file` or `@path`
+
+use std::path::Path;
+
+/// Largest file that can be attached
+const MAX_FILE_BYTES: u64 = 100 * 1024;
+
+/// Largest total size of everything attached at once
+pub const MAX_TOTAL_BYTES: usize = 400 * 1024;
+
+/// Most files a single directory or glob may attach
+const MAX_FILES: usize = 50;
+
+/// Bytes checked for NUL when detecting binary files
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// A file whose contents are sent along with the conversation
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: String,
+    pub content: String,
+}
+
+impl Attachment {
+    /// The file as a labelled fenced block
+    pub fn fenced(&self) -> String {
+        // Use a fence longer than any backtick run inside the file
+        let longest_run = self
+            .content
+            .split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        let language = Path::new(&self.path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+
+        format!(
+            "File: {}\n{}{}\n{}\n{}",
+            self.path,
+            fence,
+            language,
+            self.content.trim_end(),
+            fence
+        )
+    }
+}
+
+/// Result of attaching a path, directory or glob
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub files: Vec<Attachment>,
+    /// Paths that were skipped, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Read every file matched by `pattern`
+///
+/// `pattern` may be a file, a directory (read recursively, skipping hidden
+/// entries) or a glob such as `src/**/*.rs`.
+pub fn load(pattern: &str) -> Result<Loaded, String> {
+    let pattern = expand_home(pattern);
+    let mut paths = Vec::new();
+
+    if pattern.contains(['*', '?', '[']) {
+        let matches = glob::glob(&pattern).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+        for path in matches.flatten() {
+            if paths.len() > MAX_FILES {
+                break;
+            }
+            collect(&path, &mut paths);
+        }
+    } else {
+        let path = Path::new(&pattern);
+        if !path.exists() {
+            return Err(format!("No such file or directory: {}", pattern));
+        }
+        collect(path, &mut paths);
+    }
+
+    if paths.is_empty() {
+        return Err(format!("No files match {}", pattern));
+    }
+
+    let mut loaded = Loaded::default();
+    if paths.len() > MAX_FILES {
+        loaded.skipped.push((
+            pattern.clone(),
+            format!("matched more than {} files, only the first {} were attached", MAX_FILES, MAX_FILES),
+        ));
+        paths.truncate(MAX_FILES);
+    }
+
+    for path in paths {
+        let display = path.to_string_lossy().into_owned();
+        match read_text(&path) {
+            Ok(content) => loaded.files.push(Attachment {
+                path: display,
+                content,
+            }),
+            Err(reason) => loaded.skipped.push((display, reason)),
+        }
+    }
+    Ok(loaded)
+}
+
+/// Paths mentioned in `message` as `@path`
+///
+/// Only mentions that name an existing path or contain a glob are returned,
+/// so handles such as `@someone` are left alone.
+pub fn mentions(message: &str) -> Vec<String> {
+    message
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|path| path.trim_end_matches([',', ';', ':', '!', '?', ')', '.']))
+        .filter(|path| !path.is_empty())
+        .filter(|path| path.contains(['*', '?', '[']) || Path::new(&expand_home(path)).exists())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Add files under `path` to `paths`, recursing into directories
+///
+/// Symlinked directories inside `path` are not followed, so a link cannot
+/// loop or lead out of the tree, and the walk stops once more than
+/// `MAX_FILES` files have been found.
+fn collect(path: &Path, paths: &mut Vec<std::path::PathBuf>) {
+    if paths.len() > MAX_FILES {
+        return;
+    }
+    if path.is_file() {
+        paths.push(path.to_path_buf());
+        return;
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+    entries.sort();
+    for entry in entries {
+        if paths.len() > MAX_FILES {
+            return;
+        }
+        let hidden = entry
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        let linked_dir = entry.is_dir()
+            && std::fs::symlink_metadata(&entry).is_ok_and(|m| m.file_type().is_symlink());
+        if !hidden && !linked_dir {
+            collect(&entry, paths);
+        }
+    }
+}
+
+/// Read a file as text, refusing large and binary files
+fn read_text(path: &Path) -> Result<String, String> {
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size > MAX_FILE_BYTES {
+        return Err(format!("{} KB exceeds the {} KB limit", size / 1024, MAX_FILE_BYTES / 1024));
+    }
+
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if bytes.iter().take(BINARY_SNIFF_BYTES).any(|b| *b == 0) {
+        return Err("binary file".to_string());
+    }
+    String::from_utf8(bytes).map_err(|_| "not valid UTF-8 text".to_string())
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn collected(path: &Path) -> Vec<String> {
+        let mut paths = Vec::new();
+        collect(path, &mut paths);
+        paths
+            .iter()
+            .map(|p| p.strip_prefix(path).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn collects_directories_recursively_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("b.rs"), "b");
+        write(&dir.path().join("a/c.rs"), "c");
+        write(&dir.path().join(".git/config"), "hidden");
+        write(&dir.path().join("a/.env"), "hidden");
+
+        assert_eq!(collected(dir.path()), vec!["a/c.rs", "b.rs"]);
+    }
+
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        write(&dir.path().join("src/main.rs"), "fn main() {}");
+        write(&outside.path().join("secret.txt"), "secret");
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("src/loop")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("outside")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("src/main.rs"), dir.path().join("link.rs")).unwrap();
+
+        assert_eq!(collected(dir.path()), vec!["link.rs", "src/main.rs"]);
+    }
+
+    #[test]
+    fn stops_after_the_file_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..MAX_FILES + 20 {
+            write(&dir.path().join(format!("f{:03}.txt", i)), "x");
+        }
+        assert_eq!(collected(dir.path()).len(), MAX_FILES + 1);
+
+        let loaded = load(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(loaded.files.len(), MAX_FILES);
+        assert_eq!(loaded.skipped.len(), 1);
+    }
+
+    #[test]
+    fn binary_and_large_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("text.txt"), "hello");
+        std::fs::write(dir.path().join("binary.bin"), [0u8, 1, 2]).unwrap();
+        write(&dir.path().join("large.txt"), &"x".repeat(MAX_FILE_BYTES as usize + 1));
+
+        let loaded = load(dir.path().to_str().unwrap()).unwrap();
+        let files: Vec<&str> = loaded.files.iter().map(|f| f.content.as_str()).collect();
+        assert_eq!(files, vec!["hello"]);
+        let reasons: Vec<&str> = loaded.skipped.iter().map(|(_, reason)| reason.as_str()).collect();
+        assert_eq!(reasons, vec!["binary file", "100 KB exceeds the 100 KB limit"]);
+    }
+
+    #[test]
+    fn fences_outlast_backticks_in_the_file() {
+        let attachment = Attachment {
+            path: "notes.md".to_string(),
+            content: "```rust\nfn main() {}\n```\n".to_string(),
+        };
+        assert_eq!(attachment.fenced(), "File: notes.md\n````md\n```rust\nfn main() {}\n```\n````");
+    }
+}
@@ -0,0 +1,281 @@
+//! Batch processing of JSONL request files
+//!
+//! `dualmind batch input.jsonl -o output.jsonl` reads one OpenAI batch-style
+//! request per line (`{"custom_id": ..., "body": {"messages": [...]}}`), runs
+//! them through the pipeline with bounded concurrency and writes one result
+//! per line in input order.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::api::models::ChatCompletionRequest;
+use crate::config::Config;
+use crate::core::llm::estimate_tokens;
+use crate::core::pipeline::run_pipeline;
+
+/// Requests run at once unless `--concurrency` says otherwise
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// One line of the input file
+#[derive(Debug, Deserialize)]
+struct BatchLine {
+    #[serde(default)]
+    custom_id: Option<String>,
+    body: Value,
+}
+
+/// One line of the output file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub answer: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Options parsed from the command line
+struct BatchArgs {
+    input: PathBuf,
+    output: PathBuf,
+    concurrency: usize,
+    resume: bool,
+}
+
+impl BatchArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let usage = "Usage: dualmind batch <input.jsonl> [-o output.jsonl] [--concurrency=N] [--resume]";
+        let mut input = None;
+        let mut output = None;
+        let mut concurrency = DEFAULT_CONCURRENCY;
+        let mut resume = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-o" || arg == "--output" {
+                output = Some(PathBuf::from(args.next().ok_or(usage)?));
+            } else if let Some(path) = arg.strip_prefix("--output=") {
+                output = Some(PathBuf::from(path));
+            } else if let Some(n) = arg.strip_prefix("--concurrency=") {
+                concurrency = n.parse::<usize>().ok().filter(|n| *n > 0).ok_or(usage)?;
+            } else if arg == "--resume" {
+                resume = true;
+            } else if !arg.starts_with('-') && input.is_none() {
+                input = Some(PathBuf::from(arg));
+            }
+        }
+
+        let input = input.ok_or(usage)?;
+        let output = output.unwrap_or_else(|| input.with_extension("results.jsonl"));
+        Ok(Self {
+            input,
+            output,
+            concurrency,
+            resume,
+        })
+    }
+}
+
+/// Run `dualmind batch`
+pub async fn run(client: Client, mut config: Config, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(id) = config.pipeline.clone() {
+        let pipeline = config
+            .find_pipeline(&id)
+            .ok_or_else(|| format!("Unknown pipeline: {}", id))?;
+        config = config.for_pipeline(pipeline);
+    }
+
+    let args = BatchArgs::parse(args)?;
+    let requests = read_requests(&args.input)?;
+
+    // Results already in the output file; failed requests are run again
+    let existing = if args.output.exists() {
+        if !args.resume {
+            return Err(format!(
+                "{} already exists; pass --resume to continue it or remove it first",
+                args.output.display()
+            )
+            .into());
+        }
+        read_results(&args.output)?
+    } else {
+        HashMap::new()
+    };
+    let done: HashSet<&str> = existing
+        .values()
+        .filter(|r| r.error.is_none())
+        .map(|r| r.custom_id.as_str())
+        .collect();
+    let pending: Vec<&(String, Value)> = requests.iter().filter(|(id, _)| !done.contains(id.as_str())).collect();
+
+    eprintln!(
+        "Running {} of {} requests ({} already done, concurrency {})",
+        pending.len(),
+        requests.len(),
+        requests.len() - pending.len(),
+        args.concurrency
+    );
+
+    // Results are appended as they finish so an interrupted run can be resumed
+    let mut output = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)?;
+    // Finish a line cut short by an interrupted run so new results start cleanly
+    let previous = std::fs::read(&args.output)?;
+    if previous.last().is_some_and(|b| *b != b'\n') {
+        writeln!(output)?;
+    }
+    let total = pending.len();
+    let mut failed = 0;
+    let mut results = stream::iter(pending)
+        .map(|(id, body)| run_request(&client, &config, id, body))
+        .buffered(args.concurrency);
+    let mut finished = 0;
+    while let Some(result) = results.next().await {
+        finished += 1;
+        match &result.error {
+            None => eprintln!("[{}/{}] {} done", finished, total, result.custom_id),
+            Some(e) => {
+                failed += 1;
+                eprintln!("[{}/{}] {} failed: {}", finished, total, result.custom_id, e);
+            }
+        }
+        writeln!(output, "{}", serde_json::to_string(&result)?)?;
+        output.flush()?;
+    }
+    drop(output);
+
+    // A resumed run appends out of order; rewrite the file in input order
+    reorder(&args.output, &requests)?;
+
+    eprintln!("Wrote {} results to {}", requests.len(), args.output.display());
+    if failed > 0 {
+        return Err(format!("{} of {} requests failed", failed, total).into());
+    }
+    Ok(())
+}
+
+/// Run one request through the pipeline
+async fn run_request(client: &Client, config: &Config, custom_id: &str, body: &Value) -> BatchResult {
+    let mut result = BatchResult {
+        custom_id: custom_id.to_string(),
+        reasoning: None,
+        answer: None,
+        usage: None,
+        error: None,
+    };
+
+    // Without a `model`, requests run on the pipeline selected on the command line
+    let mut body = body.clone();
+    if body.get("model").is_none()
+        && let Some(object) = body.as_object_mut()
+    {
+        object.insert("model".to_string(), Value::String(String::new()));
+    }
+    let request: ChatCompletionRequest = match serde_json::from_value(body) {
+        Ok(request) => request,
+        Err(e) => {
+            result.error = Some(format!("Invalid request body: {}", e));
+            return result;
+        }
+    };
+
+    let mut config = if request.model.is_empty() {
+        config.clone()
+    } else {
+        match config.find_pipeline(&request.model) {
+            Some(pipeline) => config.for_pipeline(pipeline),
+            None => {
+                // Same as the API server's 404 for an unknown model
+                result.error = Some(format!("The model `{}` does not exist", request.model));
+                return result;
+            }
+        }
+    };
+    if let Some(temperature) = request.temperature {
+        config.temperature = temperature;
+    }
+    if let Some(mode) = request.reasoning_mode {
+        config.reasoning_mode = mode;
+    }
+    request.apply_reasoning_budget(&mut config.reasoning_budget);
+
+    match run_pipeline(client, &config, &request.messages, &[], None).await {
+        Ok(output) => {
+            let reasoning: Vec<&str> = output.reasoning_stages().iter().map(|s| s.content.as_str()).collect();
+            let prompt_tokens: usize = request.messages.iter().map(|m| estimate_tokens(&m.content)).sum();
+            let completion_tokens: usize = output.stages.iter().map(|s| estimate_tokens(&s.content)).sum();
+            result.reasoning = Some(reasoning.join("\n\n")).filter(|r| !r.is_empty());
+            result.answer = Some(output.answer().to_string());
+            result.usage = Some(serde_json::json!({
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+                "estimated": true,
+            }));
+        }
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result
+}
+
+/// Read the input file as `(custom_id, body)` pairs
+fn read_requests(path: &Path) -> Result<Vec<(String, Value)>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut requests = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed: BatchLine =
+            serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        let id = parsed.custom_id.unwrap_or_else(|| format!("line-{}", i + 1));
+        if !seen.insert(id.clone()) {
+            return Err(format!("{} line {}: duplicate custom_id {}", path.display(), i + 1, id));
+        }
+        requests.push((id, parsed.body));
+    }
+    Ok(requests)
+}
+
+/// Read an output file, keeping the last result for each id
+fn read_results(path: &Path) -> Result<HashMap<String, BatchResult>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(content
+        .lines()
+        // A line cut short by an interrupted run is simply run again
+        .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+        .map(|result| (result.custom_id.clone(), result))
+        .collect())
+}
+
+/// Rewrite the output file with one result per request, in input order
+fn reorder(path: &Path, requests: &[(String, Value)]) -> Result<(), Box<dyn std::error::Error>> {
+    let results = read_results(path)?;
+    let mut lines = String::new();
+    for (id, _) in requests {
+        if let Some(result) = results.get(id) {
+            lines.push_str(&serde_json::to_string(result)?);
+            lines.push('\n');
+        }
+    }
+
+    // Write alongside and rename so the results are never half written
+    let temp = path.with_extension("jsonl.tmp");
+    std::fs::write(&temp, lines)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
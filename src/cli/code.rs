@@ -0,0 +1,233 @@
+//! Fenced code blocks extracted from answers, for `/code` and `--out-dir`
+
+use std::path::{Component, Path, PathBuf};
+
+use similar::TextDiff;
+
+/// A fenced code block found in an answer
+#[derive(Debug, Clone)]
+pub struct CodeBlock {
+    pub language: String,
+    /// File name suggested by the answer, if any
+    pub filename: Option<String>,
+    pub content: String,
+}
+
+impl CodeBlock {
+    /// File name to save the block as when the answer gives none
+    pub fn default_filename(&self, number: usize) -> String {
+        format!("block-{}.{}", number, extension(&self.language))
+    }
+}
+
+/// Extract the fenced code blocks from Markdown
+///
+/// File names are taken from the info string (```` ```rust src/main.rs ````,
+/// ```` ```rust:src/main.rs ```` or `title="..."`), from a path on the line
+/// just before the fence, or from a `// path` comment on the block's first line.
+pub fn extract(markdown: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut previous_line = "";
+    let mut lines = markdown.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let fence_char = match trimmed.chars().next() {
+            Some(c @ ('`' | '~')) => c,
+            _ => {
+                if !line.trim().is_empty() {
+                    previous_line = line;
+                }
+                continue;
+            }
+        };
+        let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+        if fence_len < 3 {
+            previous_line = line;
+            continue;
+        }
+
+        let info = trimmed[fence_len..].trim();
+        let (language, info_filename) = parse_info(info);
+
+        let mut body = Vec::new();
+        for line in lines.by_ref() {
+            let closing = line.trim();
+            if closing.len() >= fence_len && closing.chars().all(|c| c == fence_char) {
+                break;
+            }
+            body.push(line);
+        }
+
+        let filename = info_filename
+            .or_else(|| path_in(previous_line))
+            .or_else(|| body.first().and_then(|first| comment_path(first)));
+        blocks.push(CodeBlock {
+            language,
+            filename,
+            content: body.join("\n") + "\n",
+        });
+        previous_line = "";
+    }
+
+    blocks
+}
+
+/// Resolve `name` inside `dir`, refusing absolute paths and `..`
+pub fn path_within(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(name);
+    let escapes = relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(format!("{} is outside the output directory", name));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Unified diff between the current file and the new contents
+pub fn diff_preview(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(path, &format!("{} (new)", path))
+        .to_string()
+}
+
+/// Split a fence info string into the language and a file name hint
+fn parse_info(info: &str) -> (String, Option<String>) {
+    let mut words = info.split_whitespace();
+    let first = words.next().unwrap_or("");
+
+    // ```rust:src/main.rs
+    if let Some((language, path)) = first.split_once(':')
+        && looks_like_path(path)
+    {
+        return (language.to_lowercase(), Some(path.to_string()));
+    }
+
+    let filename = words.find_map(|word| {
+        let value = ["title=", "file=", "filename=", "path="]
+            .iter()
+            .find_map(|key| word.strip_prefix(key))
+            .unwrap_or(word)
+            .trim_matches(['"', '\'']);
+        looks_like_path(value).then(|| value.to_string())
+    });
+    (first.to_lowercase(), filename)
+}
+
+/// A path mentioned on a line such as `**src/main.rs**` or "File: `lib.rs`"
+fn path_in(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| matches!(c, '*' | '`' | '"' | '\'' | ':' | ',' | '(' | ')')))
+        .rfind(|word| looks_like_path(word))
+        .map(str::to_string)
+}
+
+/// A path in a leading `// path` or `# path` comment
+fn comment_path(line: &str) -> Option<String> {
+    let comment = ["//", "#", "--", "/*"]
+        .iter()
+        .find_map(|prefix| line.trim().strip_prefix(prefix))?;
+    let word = comment.trim().trim_end_matches("*/").trim();
+    let word = word.strip_prefix("File:").or(word.strip_prefix("file:")).unwrap_or(word).trim();
+    looks_like_path(word).then(|| word.to_string())
+}
+
+fn looks_like_path(word: &str) -> bool {
+    let Some((stem, ext)) = word.rsplit_once('.') else {
+        return false;
+    };
+    !stem.is_empty()
+        && !ext.is_empty()
+        && ext.len() <= 10
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        && !ext.chars().all(|c| c.is_ascii_digit())
+        && !stem.ends_with('/')
+        && !word.contains("://")
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '/' | '.' | '_' | '-'))
+}
+
+fn extension(language: &str) -> &str {
+    match language {
+        "rust" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "bash" | "sh" | "shell" | "zsh" => "sh",
+        "ruby" | "rb" => "rb",
+        "c++" | "cpp" => "cpp",
+        "csharp" | "c#" | "cs" => "cs",
+        "kotlin" | "kt" => "kt",
+        "markdown" | "md" => "md",
+        "yaml" | "yml" => "yaml",
+        "" | "text" | "plaintext" => "txt",
+        other if other.len() <= 6 && other.chars().all(|c| c.is_ascii_alphanumeric()) => other,
+        _ => "txt",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_blocks_with_their_language() {
+        let blocks = extract("Intro\n\n```Rust\nfn main() {}\n```\n\ntext\n\n~~~\nplain\n~~~\n");
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].language, "rust");
+        assert_eq!(blocks[0].content, "fn main() {}\n");
+        assert_eq!(blocks[0].filename, None);
+        assert_eq!(blocks[1].language, "");
+        assert_eq!(blocks[1].content, "plain\n");
+        assert_eq!(blocks[1].default_filename(2), "block-2.txt");
+    }
+
+    #[test]
+    fn longer_fences_keep_shorter_ones_as_content() {
+        let blocks = extract("````markdown\n```rust\nfn f() {}\n```\n````\n");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].language, "markdown");
+        assert_eq!(blocks[0].content, "```rust\nfn f() {}\n```\n");
+    }
+
+    #[test]
+    fn file_names_come_from_the_info_string() {
+        let names: Vec<Option<String>> = [
+            "```rust src/main.rs\nx\n```",
+            "```rust:src/lib.rs\nx\n```",
+            "```python title=\"app.py\"\nx\n```",
+        ]
+        .iter()
+        .map(|markdown| extract(markdown)[0].filename.clone())
+        .collect();
+        assert_eq!(
+            names,
+            vec![Some("src/main.rs".into()), Some("src/lib.rs".into()), Some("app.py".into())]
+        );
+    }
+
+    #[test]
+    fn file_names_come_from_the_surrounding_text() {
+        let blocks = extract("**src/config.rs**\n\n```rust\nx\n```\n\n```python\n# scripts/run.py\nx\n```\n");
+        assert_eq!(blocks[0].filename.as_deref(), Some("src/config.rs"));
+        assert_eq!(blocks[1].filename.as_deref(), Some("scripts/run.py"));
+
+        // Version numbers and URLs are not paths
+        let blocks = extract("Needs version 1.2 from https://example.com/a.rs\n```\nx\n```\n");
+        assert_eq!(blocks[0].filename, None);
+    }
+
+    #[test]
+    fn paths_stay_within_the_directory() {
+        let dir = Path::new("out");
+        assert_eq!(path_within(dir, "src/main.rs").unwrap(), Path::new("out/src/main.rs"));
+        assert_eq!(path_within(dir, "./a.rs").unwrap(), Path::new("out/a.rs"));
+        assert!(path_within(dir, "../a.rs").is_err());
+        assert!(path_within(dir, "src/../../a.rs").is_err());
+        assert!(path_within(dir, "/etc/passwd").is_err());
+    }
+}
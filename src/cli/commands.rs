@@ -0,0 +1,141 @@
+//! Slash commands understood by the terminal chat
+
+/// Which phase `/model` changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelRole {
+    Reasoner,
+    Crafter,
+}
+
+/// A parsed slash command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Clear,
+    Retry,
+    Undo,
+    Model { role: ModelRole, name: String },
+    Temperature(f32),
+    /// Set the system prompt; an empty prompt removes it
+    System(String),
+    Save(String),
+    Load(String),
+    Reasoning(bool),
+    /// Attach a file, directory or glob
+    File(String),
+    /// List attached files
+    Files,
+    /// Detach a file, or every file when no path is given
+    Detach(Option<String>),
+    /// List the code blocks in the last answer
+    CodeList,
+    /// Save code block `number` (1-based) to `path`, or to the answer's file name
+    CodeSave { number: usize, path: Option<String> },
+    Help,
+    Exit,
+}
+
+/// Help text listing every command
+pub const HELP: &str = "Commands:
+  /clear                     Start a new conversation
+  /retry                     Regenerate the last answer
+  /undo                      Remove the last question and answer
+  /model reasoner <name>     Switch the reasoning model
+  /model crafter <name>      Switch the response model
+  /temp <value>              Set the sampling temperature
+  /system [prompt]           Set the system prompt (no prompt removes it)
+  /save <file>               Save the conversation as JSON
+  /load <file>               Load a conversation saved with /save
+  /reasoning on|off          Turn the thinking phase on or off
+  /file <path|dir|glob>      Attach local files (also: @path in a message)
+  /files                     List attached files
+  /detach [path]             Detach a file, or all files
+  /code                      List the code blocks in the last answer
+  /code save <n> [path]      Save code block n to a file
+  /help                      Show this help
+  /exit                      Quit (also: exit)";
+
+/// Parse a line of input as a command
+///
+/// Returns `None` for ordinary messages. Input counts as a command when its
+/// first word starts with `/` and contains no other `/`, so pasted paths such
+/// as `/etc/hosts` are still sent as messages.
+pub fn parse(input: &str) -> Option<Result<Command, String>> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("exit") {
+        return Some(Ok(Command::Exit));
+    }
+
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let name = name.strip_prefix('/')?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let args = args.trim();
+
+    let command = match name.to_lowercase().as_str() {
+        "clear" => Ok(Command::Clear),
+        "retry" => Ok(Command::Retry),
+        "undo" => Ok(Command::Undo),
+        "model" => parse_model(args),
+        "temp" | "temperature" => args
+            .parse::<f32>()
+            .ok()
+            .filter(|t| (0.0..=2.0).contains(t))
+            .map(Command::Temperature)
+            .ok_or_else(|| "Usage: /temp <value between 0 and 2>".to_string()),
+        "system" => Ok(Command::System(args.to_string())),
+        "save" => required(args, "Usage: /save <file>").map(Command::Save),
+        "load" => required(args, "Usage: /load <file>").map(Command::Load),
+        "reasoning" => match args.to_lowercase().as_str() {
+            "on" => Ok(Command::Reasoning(true)),
+            "off" => Ok(Command::Reasoning(false)),
+            _ => Err("Usage: /reasoning on|off".to_string()),
+        },
+        "file" | "attach" => required(args, "Usage: /file <path|dir|glob>").map(Command::File),
+        "files" => Ok(Command::Files),
+        "detach" => Ok(Command::Detach(Some(args.to_string()).filter(|a| !a.is_empty()))),
+        "code" => parse_code(args),
+        "help" | "?" => Ok(Command::Help),
+        "exit" | "quit" => Ok(Command::Exit),
+        _ => Err(format!("Unknown command /{}. Type /help for a list of commands", name)),
+    };
+    Some(command)
+}
+
+fn parse_model(args: &str) -> Result<Command, String> {
+    let usage = "Usage: /model reasoner|crafter <name>";
+    let (role, name) = args.split_once(char::is_whitespace).ok_or(usage)?;
+    let role = match role.to_lowercase().as_str() {
+        "reasoner" => ModelRole::Reasoner,
+        "crafter" => ModelRole::Crafter,
+        _ => return Err(usage.to_string()),
+    };
+    let name = required(name.trim(), usage)?;
+    Ok(Command::Model { role, name })
+}
+
+fn parse_code(args: &str) -> Result<Command, String> {
+    let usage = "Usage: /code [save <n> [path]]";
+    let mut words = args.split_whitespace();
+    match words.next() {
+        None => Ok(Command::CodeList),
+        Some(word) if word.eq_ignore_ascii_case("save") => {
+            let number = words
+                .next()
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .ok_or(usage)?;
+            let path = words.next().map(str::to_string);
+            Ok(Command::CodeSave { number, path })
+        }
+        Some(_) => Err(usage.to_string()),
+    }
+}
+
+fn required(value: &str, usage: &str) -> Result<String, String> {
+    if value.is_empty() {
+        Err(usage.to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
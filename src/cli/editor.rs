@@ -0,0 +1,123 @@
+//! Line editing and persistent history for the terminal chat
+
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, Editor, Helper, KeyCode, KeyEvent, Modifiers};
+
+/// Number of entries kept in the history file
+const MAX_HISTORY: usize = 1000;
+
+/// What the user did at the prompt
+pub enum Input {
+    Line(String),
+    /// Ctrl-C was pressed
+    Interrupted,
+    /// Ctrl-D was pressed or stdin was closed
+    Eof,
+}
+
+/// Keeps reading lines while a ``` code fence is left open
+struct InputHelper;
+
+impl Validator for InputHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let fences = ctx
+            .input()
+            .lines()
+            .filter(|line| line.trim_start().starts_with("```"))
+            .count();
+        if fences % 2 == 1 {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Completer for InputHelper {
+    type Candidate = String;
+}
+
+impl Hinter for InputHelper {
+    type Hint = String;
+}
+
+impl Highlighter for InputHelper {}
+
+impl Helper for InputHelper {}
+
+/// Line editor with history, reverse search (Ctrl-R) and multi-line input
+///
+/// Alt+Enter inserts a newline, an open ``` fence keeps the input going
+/// until it is closed, and pasted text is inserted as-is rather than
+/// submitted line by line.
+pub struct LineEditor {
+    editor: Editor<InputHelper, FileHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    /// Create an editor, loading history from `history_path` if it exists
+    pub fn new(history_path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = rustyline::Config::builder()
+            .max_history_size(MAX_HISTORY)?
+            .history_ignore_dups(true)?
+            .history_ignore_space(true)
+            .bracketed_paste(true)
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(InputHelper));
+        editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+
+        if let Some(path) = &history_path {
+            // A missing history file just means this is the first run
+            let _ = editor.load_history(path);
+        }
+
+        Ok(Self { editor, history_path })
+    }
+
+    /// Read one (possibly multi-line) message
+    pub fn read(&mut self, prompt: &str) -> Result<Input, Box<dyn std::error::Error>> {
+        let line = tokio::task::block_in_place(|| self.editor.readline(prompt));
+        match line {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    self.editor.add_history_entry(line.as_str())?;
+                    self.save_history();
+                }
+                Ok(Input::Line(line))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::Eof),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Ask a yes/no question; anything but "y" or "yes" counts as no
+    pub fn confirm(&mut self, question: &str) -> bool {
+        let prompt = format!("{} [y/N] ", question);
+        match tokio::task::block_in_place(|| self.editor.readline(&prompt)) {
+            Ok(answer) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+            Err(_) => false,
+        }
+    }
+
+    fn save_history(&mut self) {
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = self.editor.save_history(path) {
+            eprintln!("Failed to save history to {}: {}", path.display(), e);
+        }
+    }
+}
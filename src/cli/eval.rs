@@ -0,0 +1,514 @@
+//! Evaluation harness comparing pipelines on a dataset
+//!
+//! `dualmind eval dataset.jsonl --pipelines=dualmind:skip,dualmind:full` runs
+//! every item through each pipeline, scores the answers and prints accuracy,
+//! latency and token use per pipeline.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+
+use crate::cli::code;
+use crate::config::pipelines::DEFAULT_PIPELINE_ID;
+use crate::config::Config;
+use crate::core::adaptive::ReasoningMode;
+use crate::core::llm::{estimate_tokens, stream_chat_completion, to_api_messages};
+use crate::core::pipeline::run_pipeline;
+use crate::error::Error;
+use crate::models::openai::ChatMessage;
+use crate::models::{Message, Role};
+
+/// Pipelines compared when `--pipelines` is not given: reasoning skipped, then both phases
+const DEFAULT_PIPELINES: &str = "dualmind:skip,dualmind:full";
+
+/// Items evaluated at once unless `--concurrency` says otherwise
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// How long a checker command may run
+const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One dataset line
+#[derive(Debug, Clone, Deserialize)]
+struct EvalItem {
+    #[serde(default)]
+    id: Option<String>,
+    /// The question; use `messages` instead for a whole conversation
+    #[serde(default)]
+    prompt: Option<String>,
+    #[serde(default)]
+    messages: Vec<Message>,
+    /// Shorthand for an `exact` check
+    #[serde(default)]
+    expected: Option<String>,
+    #[serde(default)]
+    check: Option<Check>,
+}
+
+/// How an answer is scored
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Check {
+    /// The trimmed answer equals `expected`, ignoring case
+    Exact { expected: String },
+    /// The answer contains `expected`, ignoring case
+    Contains { expected: String },
+    /// The answer matches `pattern`
+    Regex { pattern: String },
+    /// `command` exits successfully; it gets the answer on stdin and in
+    /// `$ANSWER_FILE`, and the first code block in `$CODE_FILE`
+    Command { command: String },
+    /// A model judges the answer against `criteria`
+    Judge {
+        criteria: String,
+        #[serde(default)]
+        model: Option<String>,
+    },
+}
+
+/// The score of one answer
+#[derive(Debug, Serialize)]
+struct ItemResult {
+    id: String,
+    pipeline: String,
+    passed: bool,
+    /// Why the check failed, or the pipeline error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    latency_ms: u128,
+    tokens: usize,
+    answer: String,
+}
+
+/// A pipeline under test: a pipeline id plus an optional reasoning mode
+struct Variant {
+    label: String,
+    config: Config,
+    /// Send the plain conversation straight to the final stage's model,
+    /// with no classifier, prompt templates or reasoning
+    crafter_only: bool,
+}
+
+/// Run `dualmind eval`
+pub async fn run(client: Client, config: Config, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "Usage: dualmind eval <dataset.jsonl> [--pipelines=id[:mode],...] [--concurrency=N] [--report=report.json]\n\
+                 Judge checks without a model use each pipeline's final stage model.";
+    let dataset = args.iter().find(|a| !a.starts_with("--")).ok_or(usage)?;
+    let dataset = PathBuf::from(dataset);
+    let concurrency = match args.iter().find_map(|a| a.strip_prefix("--concurrency=")) {
+        Some(n) => n.parse::<usize>().ok().filter(|n| *n > 0).ok_or(usage)?,
+        None => DEFAULT_CONCURRENCY,
+    };
+    let specs = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--pipelines="))
+        .unwrap_or(DEFAULT_PIPELINES);
+    let report_path = args.iter().find_map(|a| a.strip_prefix("--report="));
+
+    let items = read_dataset(&dataset)?;
+    let variants = specs
+        .split(',')
+        .map(|spec| variant(&config, spec.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Checker commands run from the dataset's directory so they can use relative paths
+    let check_dir = dataset.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+    eprintln!("Evaluating {} items on {} pipelines", items.len(), variants.len());
+    let mut results = Vec::new();
+    for variant in &variants {
+        let scored: Vec<ItemResult> = stream::iter(&items)
+            .map(|item| evaluate(&client, variant, item, check_dir))
+            .buffered(concurrency)
+            .collect()
+            .await;
+        let passed = scored.iter().filter(|r| r.passed).count();
+        eprintln!("{}: {}/{} passed", variant.label, passed, scored.len());
+        results.extend(scored);
+    }
+
+    let summaries: Vec<_> = variants.iter().map(|v| summarize(&v.label, &results)).collect();
+    print_table(&summaries);
+
+    if let Some(path) = report_path {
+        let report = json!({
+            "dataset": dataset,
+            "pipelines": summaries,
+            "results": results,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        eprintln!("Wrote report to {}", path);
+    }
+    Ok(())
+}
+
+/// Build the configuration for `id[:mode]`, where mode is a reasoning mode
+/// or `crafter-only`
+fn variant(config: &Config, spec: &str) -> Result<Variant, String> {
+    let (id, mode) = spec.split_once(':').unwrap_or((spec, ""));
+    let id = if id.is_empty() { DEFAULT_PIPELINE_ID } else { id };
+    let pipeline = config
+        .find_pipeline(id)
+        .ok_or_else(|| format!("Unknown pipeline: {}", id))?;
+    let mut variant_config = config.for_pipeline(pipeline);
+
+    let crafter_only = mode == "crafter-only";
+    match mode {
+        "" | "crafter-only" => {}
+        mode => {
+            variant_config.reasoning_mode =
+                ReasoningMode::parse(mode).ok_or_else(|| format!("Unknown reasoning mode in {}: {}", spec, mode))?;
+        }
+    }
+
+    Ok(Variant {
+        label: spec.to_string(),
+        config: variant_config,
+        crafter_only,
+    })
+}
+
+/// Run one item through a pipeline and score the answer
+async fn evaluate(client: &Client, variant: &Variant, item: &(String, EvalItem), check_dir: &Path) -> ItemResult {
+    let (id, item) = item;
+    let messages = item_messages(item);
+    let mut result = ItemResult {
+        id: id.clone(),
+        pipeline: variant.label.clone(),
+        passed: false,
+        detail: None,
+        latency_ms: 0,
+        tokens: 0,
+        answer: String::new(),
+    };
+
+    // The outputs of the stages that ran, and the final answer
+    let started = Instant::now();
+    let output = if variant.crafter_only {
+        crafter_only(client, &variant.config, &messages)
+            .await
+            .map(|answer| (vec![answer.clone()], answer))
+    } else {
+        run_pipeline(client, &variant.config, &messages, &[], None)
+            .await
+            .map(|output| {
+                let stages = output
+                    .stages
+                    .iter()
+                    .filter(|s| !s.content.is_empty())
+                    .map(|s| s.content.clone())
+                    .collect();
+                (stages, output.answer().to_string())
+            })
+    };
+    result.latency_ms = started.elapsed().as_millis();
+    let (stage_outputs, answer) = match output {
+        Ok(output) => output,
+        Err(e) => {
+            result.detail = Some(format!("pipeline failed: {}", e));
+            return result;
+        }
+    };
+
+    let prompt_tokens: usize = messages.iter().map(|m| estimate_tokens(&m.content)).sum();
+    // Every stage that ran reads the prompt and writes its output
+    result.tokens = stage_outputs
+        .iter()
+        .map(|content| prompt_tokens + estimate_tokens(content))
+        .sum();
+    result.answer = answer;
+
+    let check = item.check.clone().or_else(|| {
+        item.expected
+            .clone()
+            .map(|expected| Check::Exact { expected })
+    });
+    match check {
+        Some(check) => match score(client, &variant.config, &check, &messages, &result.answer, check_dir).await {
+            Ok(()) => result.passed = true,
+            Err(reason) => result.detail = Some(reason),
+        },
+        None => result.detail = Some("no check or expected answer".to_string()),
+    }
+    result
+}
+
+/// The model of the pipeline's final stage, which writes the answer
+fn final_model(config: &Config) -> String {
+    config
+        .pipeline_stages()
+        .pop()
+        .map_or_else(|| config.craft_model.clone(), |stage| stage.model)
+}
+
+/// Ask the final stage's model the plain conversation, as a baseline
+async fn crafter_only(client: &Client, config: &Config, messages: &[Message]) -> Result<String, Error> {
+    let model = final_model(config);
+    let api_messages: Vec<ChatMessage> = messages.iter().map(ChatMessage::from).collect();
+    stream_chat_completion(client, &config.api_key, config, &model, &api_messages, |_| {}).await
+}
+
+/// Check an answer against the pipeline that wrote it, returning why it failed
+async fn score(
+    client: &Client,
+    config: &Config,
+    check: &Check,
+    messages: &[Message],
+    answer: &str,
+    check_dir: &Path,
+) -> Result<(), String> {
+    match check {
+        Check::Exact { expected } => {
+            if answer.trim().eq_ignore_ascii_case(expected.trim()) {
+                Ok(())
+            } else {
+                Err(format!("expected exactly {:?}", expected))
+            }
+        }
+        Check::Contains { expected } => {
+            if answer.to_lowercase().contains(&expected.to_lowercase()) {
+                Ok(())
+            } else {
+                Err(format!("answer does not contain {:?}", expected))
+            }
+        }
+        Check::Regex { pattern } => {
+            let regex = Regex::new(pattern).map_err(|e| format!("invalid pattern: {}", e))?;
+            if regex.is_match(answer) {
+                Ok(())
+            } else {
+                Err(format!("answer does not match /{}/", pattern))
+            }
+        }
+        Check::Command { command } => run_check_command(command, answer, check_dir).await,
+        Check::Judge { criteria, model } => {
+            let model = model.clone().unwrap_or_else(|| final_model(config));
+            judge(client, config, &model, criteria, messages, answer).await
+        }
+    }
+}
+
+/// Run a checker command against the answer
+async fn run_check_command(command: &str, answer: &str, dir: &Path) -> Result<(), String> {
+    let files = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let answer_file = files.path().join("answer.md");
+    std::fs::write(&answer_file, answer).map_err(|e| e.to_string())?;
+    let code_file = files.path().join("code");
+    let first_block = code::extract(answer).into_iter().next().map(|b| b.content);
+    std::fs::write(&code_file, first_block.unwrap_or_default()).map_err(|e| e.to_string())?;
+
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .env("ANSWER_FILE", &answer_file)
+        .env("CODE_FILE", &code_file)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run {:?}: {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A checker that ignores stdin may close it early
+        let _ = stdin.write_all(answer.as_bytes()).await;
+    }
+
+    let output = tokio::time::timeout(CHECK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("check timed out after {}s", CHECK_TIMEOUT.as_secs()))?
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+    Err(format!("check failed ({}): {}", output.status, last_line))
+}
+
+/// Ask a model whether the answer meets the criteria
+async fn judge(
+    client: &Client,
+    config: &Config,
+    model: &str,
+    criteria: &str,
+    messages: &[Message],
+    answer: &str,
+) -> Result<(), String> {
+    let question = messages.last().map_or("", |m| m.content.as_str());
+    let system_content = "You are grading an answer. Reply with PASS if the answer meets the criteria and FAIL otherwise, followed by a one-sentence reason.";
+    let prompt = Message {
+        role: Role::User,
+        content: format!("Question:\n{}\n\nCriteria:\n{}\n\nAnswer:\n{}", question, criteria, answer),
+    };
+    let api_messages = to_api_messages(system_content, &[prompt]);
+    let verdict = stream_chat_completion(client, &config.api_key, config, model, &api_messages, |_| {})
+        .await
+        .map_err(|e| format!("judge {} failed: {}", model, e))?;
+
+    let verdict = verdict.trim();
+    if verdict.to_uppercase().starts_with("PASS") {
+        Ok(())
+    } else {
+        Err(format!("judge: {}", verdict.lines().next().unwrap_or("")))
+    }
+}
+
+/// Accuracy, latency and tokens for one pipeline
+#[derive(Debug, Serialize)]
+struct Summary {
+    pipeline: String,
+    items: usize,
+    passed: usize,
+    accuracy: f64,
+    mean_latency_ms: u128,
+    p95_latency_ms: u128,
+    /// Estimated at four characters per token
+    total_tokens: usize,
+}
+
+fn summarize(label: &str, results: &[ItemResult]) -> Summary {
+    let results: Vec<&ItemResult> = results.iter().filter(|r| r.pipeline == label).collect();
+    let items = results.len();
+    let passed = results.iter().filter(|r| r.passed).count();
+    let mut latencies: Vec<u128> = results.iter().map(|r| r.latency_ms).collect();
+    latencies.sort_unstable();
+
+    Summary {
+        pipeline: label.to_string(),
+        items,
+        passed,
+        accuracy: if items == 0 { 0.0 } else { passed as f64 / items as f64 },
+        mean_latency_ms: latencies.iter().sum::<u128>() / latencies.len().max(1) as u128,
+        p95_latency_ms: latencies
+            .get((latencies.len() * 95).div_ceil(100).saturating_sub(1))
+            .copied()
+            .unwrap_or(0),
+        total_tokens: results.iter().map(|r| r.tokens).sum(),
+    }
+}
+
+fn print_table(summaries: &[Summary]) {
+    let width = summaries.iter().map(|s| s.pipeline.len()).max().unwrap_or(0).max("pipeline".len());
+    println!(
+        "{:<width$}  {:>8}  {:>9}  {:>12}  {:>11}  {:>8}",
+        "pipeline", "accuracy", "passed", "mean latency", "p95 latency", "tokens"
+    );
+    for s in summaries {
+        println!(
+            "{:<width$}  {:>7.1}%  {:>9}  {:>10}ms  {:>9}ms  {:>8}",
+            s.pipeline,
+            s.accuracy * 100.0,
+            format!("{}/{}", s.passed, s.items),
+            s.mean_latency_ms,
+            s.p95_latency_ms,
+            s.total_tokens
+        );
+    }
+}
+
+/// The conversation an item asks about
+fn item_messages(item: &EvalItem) -> Vec<Message> {
+    let mut messages = item.messages.clone();
+    if let Some(prompt) = &item.prompt {
+        messages.push(Message {
+            role: Role::User,
+            content: prompt.clone(),
+        });
+    }
+    messages
+}
+
+/// Read the dataset as `(id, item)` pairs
+fn read_dataset(path: &Path) -> Result<Vec<(String, EvalItem)>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut items = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item: EvalItem =
+            serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        if item.prompt.is_none() && item.messages.is_empty() {
+            return Err(format!("{} line {}: needs a prompt or messages", path.display(), i + 1));
+        }
+        let id = item.id.clone().unwrap_or_else(|| format!("line-{}", i + 1));
+        items.push((id, item));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Pipeline;
+    use crate::testing::{test_config, MockResponse, MockUpstream, Match, CRAFT_MODEL};
+
+    #[test]
+    fn variants_parse_the_mode() {
+        let config = test_config("http://127.0.0.1:9");
+        let skip = variant(&config, "dualmind:skip").unwrap();
+        assert_eq!(skip.config.reasoning_mode, ReasoningMode::Skip);
+        assert!(!skip.crafter_only);
+        assert!(variant(&config, ":crafter-only").unwrap().crafter_only);
+        assert!(variant(&config, "dualmind:sometimes").is_err());
+        assert!(variant(&config, "missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn crafter_only_sends_the_plain_conversation() {
+        let upstream = MockUpstream::start().await;
+        upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("4"));
+        let config = test_config(&upstream.url());
+        let variant = variant(&config, "dualmind:crafter-only").unwrap();
+        let item = EvalItem {
+            id: None,
+            prompt: Some("What is 2 + 2?".to_string()),
+            messages: Vec::new(),
+            expected: Some("4".to_string()),
+            check: None,
+        };
+
+        let result = evaluate(&Client::new(), &variant, &("1".to_string(), item), Path::new(".")).await;
+        assert!(result.passed, "{:?}", result.detail);
+
+        let requests = upstream.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model, CRAFT_MODEL);
+        let messages: Vec<(&str, &str)> = requests[0].messages().collect();
+        assert_eq!(messages, vec![("user", "What is 2 + 2?")]);
+    }
+
+    #[tokio::test]
+    async fn judges_default_to_the_final_stage_model() {
+        let upstream = MockUpstream::start().await;
+        upstream.on(Match::Model("mock-final".into()), MockResponse::text("PASS"));
+        let mut config = test_config(&upstream.url());
+        let mut pipeline = Pipeline::default_for(&config);
+        pipeline.id = "final".to_string();
+        pipeline.craft_model = "mock-final".to_string();
+        config.pipelines.push(pipeline);
+        let variant = variant(&config, "final:crafter-only").unwrap();
+        let item = EvalItem {
+            id: None,
+            prompt: Some("Say PASS".to_string()),
+            messages: Vec::new(),
+            expected: None,
+            check: Some(Check::Judge {
+                criteria: "Says PASS".to_string(),
+                model: None,
+            }),
+        };
+
+        let result = evaluate(&Client::new(), &variant, &("1".to_string(), item), Path::new(".")).await;
+        assert!(result.passed, "{:?}", result.detail);
+
+        let requests = upstream.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.model == "mock-final"));
+    }
+}
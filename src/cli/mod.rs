@@ -1,3 +1,12 @@
 //! CLI-related functionality
 
+pub mod ask;
+pub mod attachments;
+pub mod batch;
+pub mod code;
+pub mod commands;
+pub mod editor;
+pub mod eval;
+pub mod render;
+pub mod sessions;
 pub mod terminal; 
\ No newline at end of file
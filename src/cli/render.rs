@@ -0,0 +1,393 @@
+//! Streaming Markdown rendering for the terminal
+//!
+//! Content is rendered a line at a time as it streams in: headings, lists,
+//! quotes, emphasis and tables are styled with ANSI escapes and fenced code
+//! blocks are syntax highlighted by language.
+
+use std::sync::LazyLock;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const HEADING: &str = "\x1b[1;36m";
+const BULLET: &str = "\x1b[33m";
+const INLINE_CODE: &str = "\x1b[38;5;215m";
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    let mut themes = ThemeSet::load_defaults().themes;
+    themes.remove("base16-ocean.dark").unwrap_or_default()
+});
+
+/// Wrap `text` in dim styling, or return it unchanged in plain mode
+pub fn dim(text: &str, plain: bool) -> String {
+    if plain {
+        text.to_string()
+    } else {
+        format!("{DIM}{text}{RESET}")
+    }
+}
+
+/// Renders streamed Markdown, or passes it through untouched in plain mode
+pub struct MarkdownRenderer {
+    plain: bool,
+    line: String,
+    code: Option<CodeBlock>,
+    table: Vec<String>,
+}
+
+/// The fenced code block being rendered
+struct CodeBlock {
+    fence: String,
+    highlighter: HighlightLines<'static>,
+}
+
+impl MarkdownRenderer {
+    pub fn new(plain: bool) -> Self {
+        Self {
+            plain,
+            line: String::new(),
+            code: None,
+            table: Vec::new(),
+        }
+    }
+
+    /// Add streamed content, returning whatever can be printed so far
+    pub fn push(&mut self, content: &str) -> String {
+        if self.plain {
+            return content.to_string();
+        }
+
+        self.line.push_str(content);
+        let mut output = String::new();
+        while let Some(newline) = self.line.find('\n') {
+            let line: String = self.line.drain(..=newline).collect();
+            output.push_str(&self.render_line(line.trim_end_matches(['\n', '\r'])));
+        }
+        output
+    }
+
+    /// Render anything still buffered once the stream has ended
+    pub fn finish(&mut self) -> String {
+        if self.plain {
+            return String::new();
+        }
+
+        let mut output = String::new();
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            output.push_str(&self.render_line(&line));
+        }
+        output.push_str(&self.flush_table());
+        self.code = None;
+        output
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+
+        // Inside a code block everything but the closing fence is code
+        if let Some(code) = &mut self.code {
+            if is_closing_fence(trimmed, &code.fence) {
+                self.code = None;
+                return format!("{DIM}{line}{RESET}\n");
+            }
+            let highlighted = code
+                .highlighter
+                .highlight_line(&format!("{line}\n"), &SYNTAXES)
+                .map(|ranges| as_24_bit_terminal_escaped(&ranges, false))
+                .unwrap_or_else(|_| line.to_string());
+            return format!("{}{RESET}\n", highlighted.trim_end_matches('\n'));
+        }
+
+        // Tables are buffered so their columns can be aligned
+        if trimmed.starts_with('|') {
+            self.table.push(trimmed.to_string());
+            return String::new();
+        }
+        let mut output = self.flush_table();
+
+        if let Some((fence, language)) = opening_fence(trimmed) {
+            let syntax = SYNTAXES
+                .find_syntax_by_token(language)
+                .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+            self.code = Some(CodeBlock {
+                fence: fence.to_string(),
+                highlighter: HighlightLines::new(syntax, &THEME),
+            });
+            output.push_str(&format!("{DIM}{line}{RESET}\n"));
+            return output;
+        }
+
+        output.push_str(&render_block_line(line));
+        output.push('\n');
+        output
+    }
+
+    /// Render buffered table rows with aligned columns
+    fn flush_table(&mut self) -> String {
+        if self.table.is_empty() {
+            return String::new();
+        }
+
+        let rows: Vec<Vec<String>> = std::mem::take(&mut self.table)
+            .iter()
+            .map(|row| {
+                row.trim()
+                    .trim_matches('|')
+                    .split('|')
+                    .map(|cell| cell.trim().to_string())
+                    .collect()
+            })
+            .collect();
+        let is_separator = |row: &Vec<String>| {
+            row.iter()
+                .all(|cell| !cell.is_empty() && cell.chars().all(|c| matches!(c, '-' | ':' | ' ')))
+        };
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in rows.iter().filter(|r| !is_separator(r)) {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(visible_width(&render_inline(cell)));
+            }
+        }
+
+        let mut output = String::new();
+        for (index, row) in rows.iter().enumerate() {
+            if is_separator(row) {
+                let line: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+                output.push_str(&format!("{DIM}{}{RESET}\n", line.join("─┼─")));
+                continue;
+            }
+
+            let cells: Vec<String> = (0..columns)
+                .map(|i| {
+                    let cell = render_inline(row.get(i).map_or("", String::as_str));
+                    let padding = " ".repeat(widths[i].saturating_sub(visible_width(&cell)));
+                    if index == 0 {
+                        format!("{BOLD}{cell}{RESET}{padding}")
+                    } else {
+                        format!("{cell}{padding}")
+                    }
+                })
+                .collect();
+            output.push_str(&cells.join(&format!(" {DIM}│{RESET} ")));
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Split an opening fence of three or more backticks or tildes into the
+/// fence and the language named after it
+fn opening_fence(line: &str) -> Option<(&str, &str)> {
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let fence_len = line.chars().take_while(|c| *c == fence_char).count();
+    if fence_len < 3 {
+        return None;
+    }
+    let language = line[fence_len..].split_whitespace().next().unwrap_or("");
+    Some((&line[..fence_len], language))
+}
+
+/// A closing fence is at least as long as the opening one and has nothing after it
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let line = line.trim_end();
+    let fence_char = fence.chars().next().unwrap_or('`');
+    line.len() >= fence.len() && line.chars().all(|c| c == fence_char)
+}
+
+/// Style a line outside code blocks and tables
+fn render_block_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        let text = render_inline(trimmed[hashes..].trim());
+        let underline = if hashes == 1 { UNDERLINE } else { "" };
+        return format!("{HEADING}{underline}{text}{RESET}");
+    }
+
+    if trimmed.len() >= 3
+        && ["-", "*", "_"].iter().any(|c| trimmed.chars().all(|ch| ch.to_string() == *c || ch == ' '))
+    {
+        return format!("{DIM}{}{RESET}", "─".repeat(40));
+    }
+
+    if let Some(quote) = trimmed.strip_prefix('>') {
+        return format!("{indent}{DIM}│{RESET} {ITALIC}{}{RESET}", render_inline(quote.trim_start()));
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            let (checkbox, item) = match item {
+                item if item.starts_with("[ ] ") => ("☐ ", &item[4..]),
+                item if item.starts_with("[x] ") || item.starts_with("[X] ") => ("☑ ", &item[4..]),
+                item => ("", item),
+            };
+            return format!("{indent}{BULLET}•{RESET} {checkbox}{}", render_inline(item));
+        }
+    }
+
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0
+        && let Some(item) = trimmed[digits..].strip_prefix(". ")
+    {
+        return format!("{indent}{BULLET}{}.{RESET} {}", &trimmed[..digits], render_inline(item));
+    }
+
+    render_inline(line)
+}
+
+/// Style emphasis, inline code and links within a line
+fn render_inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let rest: String = chars[i..].iter().collect();
+
+        if chars[i] == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            let code = &rest[1..1 + end];
+            output.push_str(&format!("{INLINE_CODE}{code}{RESET}"));
+            i += code.chars().count() + 2;
+            continue;
+        }
+
+        if let Some(marker) = ["**", "__"].into_iter().find(|m| rest.starts_with(m))
+            && let Some(end) = rest[2..].find(marker)
+            && end > 0
+        {
+            let inner = &rest[2..2 + end];
+            output.push_str(&format!("{BOLD}{}{RESET}", render_inline(inner)));
+            i += inner.chars().count() + 4;
+            continue;
+        }
+
+        if (chars[i] == '*' || chars[i] == '_')
+            && chars.get(i + 1).is_some_and(|c| !c.is_whitespace())
+            && (i == 0 || !chars[i - 1].is_alphanumeric())
+            && let Some(end) = rest[1..].find(chars[i])
+            && end > 0
+        {
+            let inner = &rest[1..1 + end];
+            output.push_str(&format!("{ITALIC}{}{RESET}", render_inline(inner)));
+            i += inner.chars().count() + 2;
+            continue;
+        }
+
+        if chars[i] == '['
+            && let Some(close) = rest.find("](")
+            && let Some(end) = rest[close..].find(')')
+        {
+            let label = &rest[1..close];
+            let url = &rest[close + 2..close + end];
+            output.push_str(&format!("{UNDERLINE}{label}{RESET} {DIM}({url}){RESET}"));
+            i += rest[..close + end + 1].chars().count();
+            continue;
+        }
+
+        output.push(chars[i]);
+        i += 1;
+    }
+
+    output
+}
+
+/// Width of `text` on screen, ignoring ANSI escapes
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render `markdown` in one go
+    fn render(markdown: &str) -> String {
+        let mut renderer = MarkdownRenderer::new(false);
+        let mut output = renderer.push(markdown);
+        output.push_str(&renderer.finish());
+        output
+    }
+
+    #[test]
+    fn fences_name_the_language() {
+        assert_eq!(opening_fence("```rust"), Some(("```", "rust")));
+        assert_eq!(opening_fence("````rust"), Some(("````", "rust")));
+        assert_eq!(opening_fence("~~~ python title=\"a.py\""), Some(("~~~", "python")));
+        assert_eq!(opening_fence("```"), Some(("```", "")));
+        assert_eq!(opening_fence("``inline``"), None);
+    }
+
+    #[test]
+    fn longer_fences_can_contain_shorter_ones() {
+        assert!(!is_closing_fence("```", "````"));
+        assert!(is_closing_fence("````  ", "````"));
+        assert!(is_closing_fence("`````", "````"));
+        assert!(!is_closing_fence("~~~~", "````"));
+        assert!(!is_closing_fence("```rust", "```"));
+
+        // The inner fence stays code, so the heading after it is not styled
+        let output = render("````markdown\n```\n# not a heading\n```\n````\n# Heading\n");
+        assert!(!output.contains(&format!("{HEADING}{UNDERLINE}not a heading")));
+        assert!(output.contains(&format!("{HEADING}{UNDERLINE}Heading{RESET}")));
+    }
+
+    #[test]
+    fn streamed_lines_render_once_complete() {
+        let mut renderer = MarkdownRenderer::new(false);
+        assert_eq!(renderer.push("## Ti"), "");
+        assert_eq!(renderer.push("tle\n"), format!("{HEADING}Title{RESET}\n"));
+        assert_eq!(renderer.push("- item"), "");
+        assert_eq!(renderer.finish(), format!("{BULLET}•{RESET} item\n"));
+    }
+
+    #[test]
+    fn plain_mode_passes_content_through() {
+        let mut renderer = MarkdownRenderer::new(true);
+        assert_eq!(renderer.push("# **Title**\n```rust"), "# **Title**\n```rust");
+        assert_eq!(renderer.finish(), "");
+    }
+
+    #[test]
+    fn inline_styles() {
+        assert_eq!(render_inline("use `x`"), format!("use {INLINE_CODE}x{RESET}"));
+        assert_eq!(render_inline("**bold** and *it*"), format!("{BOLD}bold{RESET} and {ITALIC}it{RESET}"));
+        assert_eq!(render_inline("snake_case_name"), "snake_case_name");
+        assert_eq!(
+            render_inline("[docs](https://x.y)"),
+            format!("{UNDERLINE}docs{RESET} {DIM}(https://x.y){RESET}")
+        );
+    }
+
+    #[test]
+    fn tables_are_aligned() {
+        let output = render("| a | long |\n|---|---|\n| wide cell | b |\n");
+        let widths: Vec<usize> = output.lines().map(visible_width).collect();
+        assert_eq!(widths.len(), 3);
+        assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+    }
+}
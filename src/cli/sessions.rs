@@ -0,0 +1,199 @@
+//! Terminal sessions saved across runs, and the `sessions` command
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::models::{Message, Role};
+
+/// Longest title kept for a session, in characters
+const TITLE_CHARS: usize = 60;
+
+/// Which session the terminal chat starts with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resume {
+    New,
+    /// The most recently updated session (`--continue`)
+    Latest,
+    /// A session by id or id prefix (`--session <id>`)
+    Id(String),
+}
+
+impl Resume {
+    /// Parse `--continue`, `--session <id>` or `--session=<id>`
+    pub fn from_args(args: &[String]) -> Self {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--continue" || arg == "-c" {
+                return Resume::Latest;
+            } else if let Some(id) = arg.strip_prefix("--session=") {
+                return Resume::Id(id.to_string());
+            } else if arg == "--session"
+                && let Some(id) = args.next()
+            {
+                return Resume::Id(id.clone());
+            }
+        }
+        Resume::New
+    }
+}
+
+/// A conversation saved to disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<Message>,
+}
+
+impl SavedSession {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        }
+    }
+
+    /// The first question, shortened to fit on one line
+    pub fn title(&self) -> String {
+        let first = self
+            .messages
+            .iter()
+            .find(|m| m.role == Role::User)
+            .map_or("", |m| m.content.as_str());
+        let line = first.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.chars().count() > TITLE_CHARS {
+            format!("{}…", line.chars().take(TITLE_CHARS - 1).collect::<String>())
+        } else {
+            line
+        }
+    }
+}
+
+impl Default for SavedSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sessions stored as one JSON file each in a directory
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Save `session`, stamping its update time
+    pub fn save(&self, session: &mut SavedSession) -> Result<(), String> {
+        session.updated_at = Utc::now();
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let json = serde_json::to_string_pretty(session).map_err(|e| e.to_string())?;
+        let path = self.path(&session.id);
+        std::fs::write(&path, json).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+
+    /// Every saved session, most recently updated first
+    pub fn list(&self) -> Vec<SavedSession> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut sessions: Vec<SavedSession> = entries
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|e| read_session(&e.path()))
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+        sessions
+    }
+
+    /// The most recently updated session
+    pub fn latest(&self) -> Option<SavedSession> {
+        self.list().into_iter().next()
+    }
+
+    /// Find a session by id or unambiguous id prefix
+    pub fn find(&self, id: &str) -> Result<SavedSession, String> {
+        let matches: Vec<SavedSession> = self
+            .list()
+            .into_iter()
+            .filter(|s| s.id.starts_with(id))
+            .collect();
+        match matches.len() {
+            0 => Err(format!("No session {}", id)),
+            1 => Ok(matches.into_iter().next().unwrap()),
+            n => Err(format!("{} sessions match {}, use a longer id", n, id)),
+        }
+    }
+
+    pub fn delete(&self, id: &str) -> Result<SavedSession, String> {
+        let session = self.find(id)?;
+        std::fs::remove_file(self.path(&session.id)).map_err(|e| e.to_string())?;
+        Ok(session)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+fn read_session(path: &Path) -> Option<SavedSession> {
+    let json = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Run `dualmind sessions list|show|delete`
+pub fn run(config: &Config, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "Usage: dualmind sessions list | show <id> | delete <id>";
+    let dir = config
+        .sessions_dir
+        .as_ref()
+        .ok_or("Session saving is disabled (SESSIONS_DIR is empty)")?;
+    let store = SessionStore::new(dir);
+    let mut args = args.iter().filter(|a| !a.starts_with("--"));
+
+    match (args.next().map(String::as_str), args.next()) {
+        (Some("list") | None, _) => {
+            let sessions = store.list();
+            if sessions.is_empty() {
+                println!("No saved sessions in {}", dir);
+            }
+            for session in sessions {
+                println!(
+                    "{}  {}  {:>3} messages  {}",
+                    session.id,
+                    session.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                    session.messages.len(),
+                    session.title()
+                );
+            }
+        }
+        (Some("show"), Some(id)) => {
+            let session = store.find(id)?;
+            println!("Session {} ({})\n", session.id, session.title());
+            for message in &session.messages {
+                let speaker = match message.role {
+                    Role::System => "System",
+                    Role::User => "You",
+                    Role::Assistant => "Assistant",
+                };
+                println!("{}: {}\n", speaker, message.content.trim_end());
+            }
+        }
+        (Some("delete" | "rm"), Some(id)) => {
+            let session = store.delete(id)?;
+            println!("Deleted session {} ({})", session.id, session.title());
+        }
+        _ => return Err(usage.into()),
+    }
+    Ok(())
+}
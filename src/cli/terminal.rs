@@ -1,84 +1,610 @@
 //! Terminal interface implementation
 
 use std::io::Write;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
 use reqwest::Client;
 
+use crate::api::models::Chat;
+use crate::cli::attachments::{self, Attachment, MAX_TOTAL_BYTES};
+use crate::cli::code;
+use crate::cli::commands::{self, Command, ModelRole};
+use crate::cli::editor::{Input, LineEditor};
+use crate::cli::render::{self, MarkdownRenderer};
+use crate::cli::sessions::{Resume, SavedSession, SessionStore};
 use crate::config::Config;
-use crate::core::llm::{
-    call_reasoner_with_context, is_coding_request, stream_crafter_response,
-};
+use crate::core::classifier::TaskCategory;
+use crate::core::adaptive::ReasoningMode;
+use crate::core::pipeline::{run_pipeline, PipelineEvent, StageOutput};
 use crate::models::{Message, Role};
 
 /// Start the terminal interface
 pub async fn start(
     client: Client,
-    config: Config,
+    mut config: Config,
+    resume: Resume,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // Switch to the requested pipeline, if any
+    if let Some(id) = config.pipeline.clone() {
+        let pipeline = config
+            .find_pipeline(&id)
+            .ok_or_else(|| format!("Unknown pipeline: {}", id))?;
+        config = config.for_pipeline(pipeline);
+    }
+
     println!("🤖 DualMind Chat Interface");
-    println!("Type 'exit' to quit\n");
+    println!("Type /help for commands or 'exit' to quit\n");
     println!("TIP: Run with --api to start the API server instead");
 
-    // Create a simple session for the terminal interface
-    let mut session_messages: Vec<Message> = Vec::new();
+    let mut editor = LineEditor::new(config.history_file.as_ref().map(PathBuf::from))?;
+    let mut chat = TerminalChat::new(config);
+    chat.resume(resume)?;
 
     loop {
-        print!("You: ");
-        std::io::stdout().flush()?;
-
-        let mut message = String::new();
-        let mut stdin = BufReader::new(tokio::io::stdin());
-        stdin.read_line(&mut message).await?;
+        // Ctrl-C right after cancelling a turn, or twice in a row, exits
+        let message = match editor.read("You: ")? {
+            Input::Line(message) => message,
+            Input::Interrupted if !chat.interrupted => {
+                println!("Press Ctrl-C again to exit");
+                chat.interrupted = true;
+                continue;
+            }
+            Input::Interrupted | Input::Eof => break,
+        };
+        chat.interrupted = false;
         let message = message.trim();
+        if message.is_empty() {
+            continue;
+        }
 
-        if message.eq_ignore_ascii_case("exit") {
-            break;
+        match commands::parse(message) {
+            Some(Ok(Command::Exit)) => break,
+            Some(Ok(command)) => {
+                chat.handle_command(&client, &mut editor, command).await;
+                chat.save_session();
+                continue;
+            }
+            Some(Err(e)) => {
+                println!("{}", e);
+                continue;
+            }
+            None => {}
         }
 
-        // Add user message to session
-        session_messages.push(Message {
-            role: Role::User,
-            content: message.to_string(),
-        });
+        // Attach any files mentioned as @path
+        for path in attachments::mentions(message) {
+            chat.attach(&path);
+        }
+
+        chat.ask(&client, message).await;
+        chat.save_session();
+    }
+
+    Ok(())
+}
+
+/// Marker appended to answers cut short by Ctrl-C
+const INTERRUPTED_MARKER: &str = "[interrupted]";
+
+/// State of a terminal conversation
+struct TerminalChat {
+    config: Config,
+    /// Reasoning mode restored by `/reasoning on`
+    reasoning_mode: ReasoningMode,
+    session_messages: Vec<Message>,
+    previous_reasoning: Vec<StageOutput>,
+    /// Whether the last turn was cancelled with Ctrl-C
+    interrupted: bool,
+    /// Files sent along with every turn until detached
+    attachments: Vec<Attachment>,
+    /// Where the conversation is saved, if saving is enabled
+    store: Option<SessionStore>,
+    session: SavedSession,
+}
+
+impl TerminalChat {
+    fn new(config: Config) -> Self {
+        let reasoning_mode = match config.reasoning_mode {
+            ReasoningMode::Skip => ReasoningMode::Auto,
+            mode => mode,
+        };
+
+        Self {
+            reasoning_mode,
+            session_messages: Vec::new(),
+            previous_reasoning: Vec::new(),
+            interrupted: false,
+            attachments: Vec::new(),
+            store: config.sessions_dir.as_ref().map(SessionStore::new),
+            session: SavedSession::new(),
+            config,
+        }
+    }
+
+    /// Reopen a saved session
+    fn resume(&mut self, resume: Resume) -> Result<(), String> {
+        let store = match (&resume, &self.store) {
+            (Resume::New, _) => return Ok(()),
+            (_, Some(store)) => store,
+            (_, None) => return Err("Session saving is disabled (SESSIONS_DIR is empty)".to_string()),
+        };
+        let session = match resume {
+            Resume::Id(id) => store.find(&id)?,
+            _ => match store.latest() {
+                Some(session) => session,
+                None => {
+                    println!("No saved sessions, starting a new one\n");
+                    return Ok(());
+                }
+            },
+        };
+
+        println!("Resumed session {} ({} messages): {}\n", session.id, session.messages.len(), session.title());
+        self.session_messages = session.messages.clone();
+        self.session = session;
+        Ok(())
+    }
 
-        // Check if this is a coding request
-        let is_coding = is_coding_request(&message);
+    /// Save the conversation, once it has anything in it
+    fn save_session(&mut self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if self.session_messages.is_empty() || self.session.messages == self.session_messages {
+            return;
+        }
+        self.session.messages = self.session_messages.clone();
+        if let Err(e) = store.save(&mut self.session) {
+            eprintln!("{}", e);
+        }
+    }
 
-        // First call to reasoning model
-        println!("\n🧠 Thinking phase ({} reasoning)...", config.reasoning_model);
-        let reasoning = match call_reasoner_with_context(&client, &config.api_key, &session_messages, &config).await
-        {
-            Ok(result) => result,
+    /// Run a slash command
+    async fn handle_command(&mut self, client: &Client, editor: &mut LineEditor, command: Command) {
+        match command {
+            Command::Clear => {
+                self.session_messages.clear();
+                self.previous_reasoning.clear();
+                self.attachments.clear();
+                // The cleared conversation stays saved; start a new session
+                self.session = SavedSession::new();
+                println!("Conversation cleared");
+            }
+            Command::Retry => self.retry(client).await,
+            Command::Undo => {
+                if self.session_messages.last().is_some_and(|m| m.role == Role::Assistant) {
+                    self.session_messages.pop();
+                }
+                match self.session_messages.pop() {
+                    Some(message) if message.role == Role::User => println!("Removed the last exchange"),
+                    Some(message) => {
+                        self.session_messages.push(message);
+                        println!("Nothing to undo");
+                    }
+                    None => println!("Nothing to undo"),
+                }
+                self.previous_reasoning.clear();
+            }
+            Command::Model { role, name } => {
+                self.set_model(role, &name);
+                let phase = match role {
+                    ModelRole::Reasoner => "Reasoning",
+                    ModelRole::Crafter => "Response",
+                };
+                println!("{} model set to {}", phase, name);
+            }
+            Command::Temperature(temperature) => {
+                self.config.temperature = temperature;
+                println!("Temperature set to {}", temperature);
+            }
+            Command::System(prompt) => {
+                self.session_messages.retain(|m| m.role != Role::System);
+                if prompt.is_empty() {
+                    println!("System prompt removed");
+                } else {
+                    self.session_messages.insert(0, Message {
+                        role: Role::System,
+                        content: prompt,
+                    });
+                    println!("System prompt set");
+                }
+            }
+            Command::Save(path) => {
+                let chat = Chat {
+                    messages: self.session_messages.clone(),
+                };
+                let result = serde_json::to_string_pretty(&chat)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+                match result {
+                    Ok(()) => println!("Saved {} messages to {}", self.session_messages.len(), path),
+                    Err(e) => println!("Failed to save {}: {}", path, e),
+                }
+            }
+            Command::Load(path) => {
+                let result = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| serde_json::from_str::<Chat>(&json).map_err(|e| e.to_string()));
+                match result {
+                    Ok(chat) => {
+                        self.session_messages = chat.messages;
+                        self.previous_reasoning.clear();
+                        println!("Loaded {} messages from {}", self.session_messages.len(), path);
+                    }
+                    Err(e) => println!("Failed to load {}: {}", path, e),
+                }
+            }
+            Command::Reasoning(enabled) => {
+                if enabled {
+                    self.config.reasoning_mode = self.reasoning_mode;
+                    println!("Thinking phase on");
+                } else {
+                    self.config.reasoning_mode = ReasoningMode::Skip;
+                    println!("Thinking phase off");
+                }
+            }
+            Command::File(pattern) => self.attach(&pattern),
+            Command::Files => {
+                if self.attachments.is_empty() {
+                    println!("No files attached");
+                }
+                for attachment in &self.attachments {
+                    println!(
+                        "  📎 {} ({} lines, {} bytes)",
+                        attachment.path,
+                        attachment.content.lines().count(),
+                        attachment.content.len()
+                    );
+                }
+            }
+            Command::Detach(None) => {
+                println!("Detached {} files", self.attachments.len());
+                self.attachments.clear();
+            }
+            Command::Detach(Some(path)) => {
+                let before = self.attachments.len();
+                self.attachments
+                    .retain(|a| a.path != path && !Path::new(&a.path).starts_with(&path));
+                match before - self.attachments.len() {
+                    0 => println!("{} is not attached", path),
+                    n => println!("Detached {} files", n),
+                }
+            }
+            Command::CodeList => {
+                let blocks = self.last_code_blocks();
+                if blocks.is_empty() {
+                    println!("No code blocks in the last answer");
+                }
+                for (i, block) in blocks.iter().enumerate() {
+                    let language = if block.language.is_empty() { "text" } else { &block.language };
+                    let filename = block.filename.as_deref().map(|f| format!(" → {}", f)).unwrap_or_default();
+                    let first_line = block.content.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+                    println!(
+                        "  [{}] {} ({} lines){}  {}",
+                        i + 1,
+                        language,
+                        block.content.lines().count(),
+                        filename,
+                        render::dim(first_line.trim(), self.config.plain)
+                    );
+                }
+            }
+            Command::CodeSave { number, path } => self.save_code_block(editor, number, path),
+            Command::Help => println!("{}", commands::HELP),
+            Command::Exit => {}
+        }
+    }
+
+    /// Attach the files matched by `pattern`, replacing earlier copies
+    fn attach(&mut self, pattern: &str) {
+        let loaded = match attachments::load(pattern) {
+            Ok(loaded) => loaded,
             Err(e) => {
-                eprintln!("Error in thinking phase: {}", e);
+                println!("{}", e);
+                return;
+            }
+        };
+
+        for (path, reason) in loaded.skipped {
+            println!("  ⚠️  Skipped {}: {}", path, reason);
+        }
+        for file in loaded.files {
+            self.attachments.retain(|a| a.path != file.path);
+            let total: usize = self.attachments.iter().map(|a| a.content.len()).sum();
+            if total + file.content.len() > MAX_TOTAL_BYTES {
+                println!("  ⚠️  Skipped {}: attachments would exceed {} KB", file.path, MAX_TOTAL_BYTES / 1024);
                 continue;
             }
+            println!("  📎 Attached {}", file.path);
+            self.attachments.push(file);
+        }
+    }
+
+    /// Session messages with the attached files prepended to the latest question
+    fn context_messages(&self) -> Vec<Message> {
+        let mut messages = self.session_messages.clone();
+        if self.attachments.is_empty() {
+            return messages;
+        }
+
+        if let Some(last) = messages.iter_mut().rev().find(|m| m.role == Role::User) {
+            let files: Vec<String> = self.attachments.iter().map(Attachment::fenced).collect();
+            last.content = format!("Attached files:\n\n{}\n\n{}", files.join("\n\n"), last.content);
+        }
+        messages
+    }
+
+    /// Code blocks in the last assistant message
+    fn last_code_blocks(&self) -> Vec<code::CodeBlock> {
+        self.session_messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::Assistant)
+            .map(|m| code::extract(&m.content))
+            .unwrap_or_default()
+    }
+
+    /// Write a code block to disk, confirming with a diff before overwriting
+    fn save_code_block(&self, editor: &mut LineEditor, number: usize, path: Option<String>) {
+        let blocks = self.last_code_blocks();
+        let Some(block) = blocks.get(number - 1) else {
+            println!("No code block {} in the last answer (found {})", number, blocks.len());
+            return;
+        };
+        // A name chosen by the model must stay inside the working directory;
+        // one typed by the user is taken as it is
+        let path = match (path, &block.filename) {
+            (Some(path), _) => path,
+            (None, Some(filename)) => match code::path_within(Path::new("."), filename) {
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(e) => {
+                    println!("{}; use /code save {} <path>", e, number);
+                    return;
+                }
+            },
+            (None, None) => {
+                println!("The answer gives no file name for block {}; use /code save {} <path>", number, number);
+                return;
+            }
         };
 
-        // Second call to crafting model with streaming output
-        if is_coding {
-            println!("\n💻 Execution phase ({} coding)...", config.craft_model);
-        } else {
-            println!("\n💬 Response phase ({})...", config.craft_model);
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            if existing == block.content {
+                println!("{} is already up to date", path);
+                return;
+            }
+            print!("{}", code::diff_preview(&path, &existing, &block.content));
+            if !editor.confirm(&format!("Overwrite {}?", path)) {
+                println!("Not saved");
+                return;
+            }
         }
-        println!("\nAssistant: ");
 
-        // Stream the crafter response directly to the user
-        match stream_crafter_response(&client, &config.api_key, &session_messages, &reasoning, &config).await {
-            Ok(final_response) => {
-                println!(); // Add a newline after the streamed response
+        if let Some(parent) = Path::new(&path).parent().filter(|p| !p.as_os_str().is_empty()) {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match std::fs::write(&path, &block.content) {
+            Ok(()) => println!("Wrote {} lines to {}", block.content.lines().count(), path),
+            Err(e) => println!("Failed to write {}: {}", path, e),
+        }
+    }
+
+    /// Change the model of the reasoning stages or of the final stage
+    fn set_model(&mut self, role: ModelRole, name: &str) {
+        match role {
+            ModelRole::Reasoner => self.config.reasoning_model = name.to_string(),
+            ModelRole::Crafter => self.config.craft_model = name.to_string(),
+        }
+
+        // Pipelines with explicit stages name their models per stage
+        let stage_count = self.config.stages.len();
+        for (index, stage) in self.config.stages.iter_mut().enumerate() {
+            let is_final = index + 1 == stage_count;
+            if is_final == (role == ModelRole::Crafter) {
+                stage.model = name.to_string();
+            }
+        }
+    }
+
+    /// Ask a new question
+    ///
+    /// A question that fails is dropped again, so no unanswered question is
+    /// left behind; it can simply be sent again.
+    async fn ask(&mut self, client: &Client, message: &str) {
+        self.session_messages.push(Message {
+            role: Role::User,
+            content: message.to_string(),
+        });
+        if !self.run_turn(client).await {
+            self.session_messages.pop();
+        }
+    }
+
+    /// Answer the last question again, keeping the old answer if that fails
+    async fn retry(&mut self, client: &Client) {
+        let previous = self.session_messages.pop_if(|m| m.role == Role::Assistant);
+        if !self.session_messages.last().is_some_and(|m| m.role == Role::User) {
+            println!("Nothing to retry");
+            self.session_messages.extend(previous);
+            return;
+        }
+        if !self.run_turn(client).await {
+            self.session_messages.extend(previous);
+        }
+    }
+
+    /// Run the pipeline over the session and record the answer
+    ///
+    /// Returns false when the pipeline failed and nothing was recorded.
+    async fn run_turn(&mut self, client: &Client) -> bool {
+        let config = &self.config;
+        let messages = self.context_messages();
+
+        // Run the pipeline, printing each stage as it streams
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let final_index = config.pipeline_stages().len() - 1;
+        let plain = config.plain;
+        let mut renderer = MarkdownRenderer::new(plain);
+        // Answer text streamed so far, kept if the turn is interrupted
+        let mut partial = String::new();
+        let print_events = async {
+            let mut category = TaskCategory::General;
+            while let Some(event) = events_rx.recv().await {
+                match event {
+                    PipelineEvent::TaskClassified { category: classified } => category = classified,
+                    PipelineEvent::ReasoningDecided { mode, reason } => match mode {
+                        ReasoningMode::Skip => println!("\n⚡ Skipping thinking phase ({})", reason),
+                        ReasoningMode::Brief => println!("\n⚡ Brief thinking phase ({})", reason),
+                        _ => {}
+                    },
+                    PipelineEvent::StageStarted { index, model, .. } if index == final_index => {
+                        if category == TaskCategory::Coding {
+                            println!("\n💻 Execution phase ({} coding)...", model);
+                        } else {
+                            println!("\n💬 Response phase ({} {})...", model, category);
+                        }
+                        println!("\nAssistant: ");
+                        renderer = MarkdownRenderer::new(plain);
+                        partial.clear();
+                    }
+                    PipelineEvent::StageStarted { name, model, .. } => {
+                        println!("\n🧠 Thinking phase ({} {})...", model, name);
+                    }
+                    PipelineEvent::Content { index, content } if index == final_index => {
+                        print!("{}", renderer.push(&content));
+                        partial.push_str(&content);
+                        let _ = std::io::stdout().flush();
+                    }
+                    PipelineEvent::Content { content, .. } => {
+                        print!("{}", render::dim(&content, plain));
+                        let _ = std::io::stdout().flush();
+                    }
+                    PipelineEvent::StageFinished { index } if index == final_index => {
+                        print!("{}", renderer.finish());
+                        println!();
+                    }
+                    PipelineEvent::StageFinished { .. } => println!(),
+                    PipelineEvent::StageCutOff { .. } => {
+                        print!("\n⏱️  Reasoning budget reached, moving on");
+                    }
+                    PipelineEvent::EnsembleMemberFinished { model, error, .. } => match error {
+                        None => println!("  ✓ {} finished", model),
+                        Some(e) => println!("  ✗ {} failed: {}", model, e),
+                    },
+                    PipelineEvent::CritiqueStarted { round, model } => {
+                        println!("\n🔍 Critique round {} ({})...", round, model);
+                    }
+                    PipelineEvent::CritiqueFinished { critique, .. } => {
+                        if critique.approved {
+                            println!("✅ Answer approved");
+                        } else {
+                            println!("⚠️  Issues found, refining:\n{}", critique.content);
+                        }
+                    }
+                }
+            }
+        };
+
+        // Ctrl-C drops the pipeline, which aborts any request still streaming
+        let pipeline = async {
+            tokio::join!(
+                run_pipeline(client, config, &messages, &self.previous_reasoning, Some(events_tx)),
+                print_events
+            )
+        };
+        let result = tokio::select! {
+            (result, ()) = pipeline => Some(result),
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        self.interrupted = result.is_none();
+
+        match result {
+            None => {
+                print!("{}", renderer.finish());
+                println!("\n⏹️  Interrupted");
+
+                // Keep whatever was answered so far so /retry and the next turn can see it
+                let content = if partial.trim().is_empty() {
+                    INTERRUPTED_MARKER.to_string()
+                } else {
+                    format!("{}\n\n{}", partial.trim_end(), INTERRUPTED_MARKER)
+                };
+                self.session_messages.push(Message {
+                    role: Role::Assistant,
+                    content,
+                });
+                true
+            }
+            Some(Ok(output)) => {
+                self.previous_reasoning = output.reasoning_stages().to_vec();
 
                 // Add assistant response to session
-                session_messages.push(Message {
+                self.session_messages.push(Message {
                     role: Role::Assistant,
-                    content: final_response,
+                    content: output.answer().to_string(),
                 });
+                true
             }
-            Err(e) => {
-                eprintln!("Error in execution phase: {}", e);
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                false
             }
         }
     }
+}
 
-    Ok(())
-} 
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test_config, MockResponse, MockUpstream, Match, CRAFT_MODEL};
+
+    fn message(role: Role, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_questions_are_dropped() {
+        let upstream = MockUpstream::start().await;
+        upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::error(500, "crafter exploded"));
+        let mut chat = TerminalChat::new(test_config(&upstream.url()));
+
+        chat.ask(&Client::new(), "Hello").await;
+        assert!(chat.session_messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_retries_keep_the_previous_answer() {
+        let upstream = MockUpstream::start().await;
+        upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::text("First answer"));
+        let client = Client::new();
+        let mut chat = TerminalChat::new(test_config(&upstream.url()));
+        chat.ask(&client, "Hello").await;
+
+        upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::error(500, "crafter exploded"));
+        chat.retry(&client).await;
+        assert_eq!(
+            chat.session_messages,
+            vec![message(Role::User, "Hello"), message(Role::Assistant, "First answer")]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_replace_the_previous_answer() {
+        let upstream = MockUpstream::start().await;
+        upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::text("First answer"));
+        upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("Second answer"));
+        let client = Client::new();
+        let mut chat = TerminalChat::new(test_config(&upstream.url()));
+        chat.ask(&client, "Hello").await;
+        assert_eq!(chat.session_messages[1].content, "First answer");
+
+        chat.retry(&client).await;
+        assert_eq!(
+            chat.session_messages,
+            vec![message(Role::User, "Hello"), message(Role::Assistant, "Second answer")]
+        );
+    }
+}
@@ -2,59 +2,43 @@
 
 use serde_json::json;
 
+use crate::core::pipeline::CritiqueRound;
+use crate::models::openai::{AssistantMessage, ChatChunk};
+
 /// Format a streaming chunk for OpenAI-compatible clients
 pub fn format_openai_chunk(content: &str, completion_id: &str, created_timestamp: u64, model: &str) -> String {
-    let chunk_json = json!({
-        "id": completion_id,
-        "object": "chat.completion.chunk",
-        "created": created_timestamp,
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "delta": {
-                "content": content
-            },
-            "finish_reason": null
-        }]
-    });
-    
-    format!("data: {}\n\n", chunk_json.to_string())
+    let delta = AssistantMessage {
+        content: Some(content.to_string()),
+        ..Default::default()
+    };
+    format_event(&ChatChunk::new(completion_id, created_timestamp as i64, model, delta))
 }
 
 /// Format the initial role message for OpenAI-compatible clients
 pub fn format_openai_role_chunk(completion_id: &str, created_timestamp: u64, model: &str) -> String {
-    let chunk_json = json!({
-        "id": completion_id,
-        "object": "chat.completion.chunk",
-        "created": created_timestamp,
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "delta": {
-                "role": "assistant"
-            },
-            "finish_reason": null
-        }]
-    });
-    
-    format!("data: {}\n\n", chunk_json.to_string())
+    let delta = AssistantMessage {
+        role: Some("assistant".to_string()),
+        ..Default::default()
+    };
+    format_event(&ChatChunk::new(completion_id, created_timestamp as i64, model, delta))
+}
+
+/// Format a chunk carrying the critique trail for OpenAI-compatible clients
+pub fn format_openai_critique_chunk(critiques: &[CritiqueRound], completion_id: &str, created_timestamp: u64, model: &str) -> String {
+    let mut chunk = ChatChunk::new(completion_id, created_timestamp as i64, model, AssistantMessage::default());
+    chunk.extra.insert("critique".to_string(), json!(critiques));
+    format_event(&chunk)
 }
 
 /// Format the final message with finish_reason for OpenAI-compatible clients
 pub fn format_openai_finish_chunk(completion_id: &str, created_timestamp: u64, model: &str) -> String {
-    let chunk_json = json!({
-        "id": completion_id,
-        "object": "chat.completion.chunk",
-        "created": created_timestamp,
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "delta": {},
-            "finish_reason": "stop"
-        }]
-    });
-    
-    format!("data: {}\n\n", chunk_json.to_string())
+    let mut chunk = ChatChunk::new(completion_id, created_timestamp as i64, model, AssistantMessage::default());
+    chunk.choices[0].finish_reason = Some("stop".to_string());
+    format_event(&chunk)
+}
+
+fn format_event(chunk: &ChatChunk) -> String {
+    format!("data: {}\n\n", json!(chunk))
 }
 
 /// Format the [DONE] message
@@ -71,7 +55,3 @@ pub fn is_openrouter(api_url: &str) -> bool {
 pub fn is_litellm(api_url: &str) -> bool {
     api_url.contains("litellm")
 }
-
-pub fn get_system_prompt() -> String {
-    "You are DualMind, an AI assistant powered by a reasoning model and a crafting model...".to_string()
-} 
\ No newline at end of file
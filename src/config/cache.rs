@@ -0,0 +1,45 @@
+//! Response cache settings
+
+use serde::{Deserialize, Serialize};
+
+/// Where cached responses are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Memory,
+    Disk,
+}
+
+impl CacheBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheBackend::Memory => "memory",
+            CacheBackend::Disk => "disk",
+        }
+    }
+}
+
+/// Cache settings from the `cache` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub backend: CacheBackend,
+    /// Maximum number of cached responses
+    pub capacity: usize,
+    /// Directory used by the disk backend
+    pub dir: String,
+    /// Also cache requests sampled with `temperature > 0`
+    pub cache_nonzero_temperature: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackend::Memory,
+            capacity: 1000,
+            dir: "./.dualmind-cache".to_string(),
+            cache_nonzero_temperature: false,
+        }
+    }
+}
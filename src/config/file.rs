@@ -0,0 +1,41 @@
+//! JSON configuration file support
+
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::config::cache::CacheConfig;
+use crate::config::pipelines::Pipeline;
+use crate::core::classifier::ClassifierConfig;
+
+/// Default location of the configuration file
+pub const DEFAULT_CONFIG_FILE: &str = "./dualmind.json";
+
+/// Settings that are too structured for environment variables
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub pipelines: Vec<Pipeline>,
+    pub classifier: ClassifierConfig,
+    pub cache: Option<CacheConfig>,
+}
+
+impl ConfigFile {
+    /// Load the configuration file at `path`
+    ///
+    /// A missing file is only an error when the path was set explicitly.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let (path, explicit) = match path {
+            Some(path) => (path, true),
+            None => (DEFAULT_CONFIG_FILE, false),
+        };
+
+        if !explicit && !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse config file {}: {}", path, e))
+    }
+}
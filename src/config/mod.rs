@@ -2,19 +2,27 @@
 
 mod settings;
 pub mod aisettings;
+pub mod cache;
+pub mod file;
+pub mod pipelines;
 
-pub use settings::Config;
+pub use pipelines::Pipeline;
+pub use settings::{cli_args, Config};
 
 use dotenv::dotenv;
 
 /// Load configuration from command line arguments and environment variables
 pub fn load_from_args() -> Result<Config, String> {
     dotenv().ok();
-    Ok(Config::from_args())
+    let mut config = Config::from_args()?;
+    config.load_file()?;
+    Ok(config)
 }
 
 /// Load configuration from command line arguments and environment variables
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     dotenv().ok();
-    Ok(Config::from_args())
+    let mut config = Config::from_args()?;
+    config.load_file()?;
+    Ok(config)
 } 
\ No newline at end of file
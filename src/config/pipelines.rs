@@ -0,0 +1,180 @@
+//! Named model pipelines exposed through `/v1/models`
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Pipeline used when no other pipeline is requested
+pub const DEFAULT_PIPELINE_ID: &str = "dualmind";
+
+/// A named reasoner + crafter combination selectable via the request `model`
+///
+/// Pipelines that declare `stages` run those instead of the two-phase flow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub reasoning_model: String,
+    #[serde(default)]
+    pub craft_model: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Extra reasoning models run concurrently with `reasoning_model`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning_ensemble: Vec<String>,
+    /// Model that synthesises the ensemble's reasoning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregator_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<Stage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critique: Option<CritiqueConfig>,
+    /// Directory of prompt templates that override the global ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts_dir: Option<String>,
+}
+
+/// Verification pass that checks the final answer and asks for refinements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CritiqueConfig {
+    /// Model that reviews the answer; defaults to the reasoning model
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Maximum number of critique → refine rounds
+    #[serde(default = "default_critique_rounds")]
+    pub max_rounds: u32,
+}
+
+fn default_critique_rounds() -> u32 {
+    1
+}
+
+/// One model call in a pipeline
+///
+/// The last stage produces the answer; earlier stages only feed later ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stage {
+    pub name: String,
+    pub model: String,
+    /// System prompt template; `{{<stage name>}}` is replaced by that stage's output
+    /// and the prompt template variables are available too
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// Names of earlier stages whose outputs this stage receives
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Extra models run concurrently with `model`, their outputs merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ensemble: Vec<String>,
+    /// Model that synthesises ensemble outputs; without one they are concatenated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregator: Option<String>,
+}
+
+impl Stage {
+    /// Every model this stage calls
+    pub fn models(&self) -> Vec<String> {
+        std::iter::once(self.model.clone())
+            .chain(self.ensemble.iter().cloned())
+            .collect()
+    }
+}
+
+impl Pipeline {
+    /// Check that stage names are unique and inputs refer to earlier stages
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, stage) in self.stages.iter().enumerate() {
+            let earlier = &self.stages[..index];
+            if earlier.iter().any(|s| s.name == stage.name) {
+                return Err(format!("Pipeline {}: duplicate stage name {}", self.id, stage.name));
+            }
+            if let Some(input) = stage.inputs.iter().find(|i| !earlier.iter().any(|s| &s.name == *i)) {
+                return Err(format!(
+                    "Pipeline {}: stage {} takes input {} which is not an earlier stage",
+                    self.id, stage.name, input
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The built-in pipeline backed by the reasoning and crafting models from the environment
+    pub fn default_for(config: &Config) -> Self {
+        Self {
+            id: DEFAULT_PIPELINE_ID.to_string(),
+            description: Some("Default reasoner + crafter pipeline".to_string()),
+            reasoning_model: config.reasoning_model.clone(),
+            craft_model: config.craft_model.clone(),
+            temperature: None,
+            reasoning_ensemble: config.reasoning_ensemble.clone(),
+            aggregator_model: config.aggregator_model.clone(),
+            stages: Vec::new(),
+            critique: config.critique.clone(),
+            prompts_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Find a pipeline by id
+    pub fn find_pipeline(&self, id: &str) -> Option<&Pipeline> {
+        self.pipelines.iter().find(|p| p.id == id)
+    }
+
+    /// Build the configuration to use for a request routed to `pipeline`
+    pub fn for_pipeline(&self, pipeline: &Pipeline) -> Config {
+        let mut config = self.clone();
+        if !pipeline.reasoning_model.is_empty() {
+            config.reasoning_model = pipeline.reasoning_model.clone();
+        }
+        if !pipeline.craft_model.is_empty() {
+            config.craft_model = pipeline.craft_model.clone();
+        }
+        if let Some(temperature) = pipeline.temperature {
+            config.temperature = temperature;
+        }
+        config.reasoning_ensemble = pipeline.reasoning_ensemble.clone();
+        config.aggregator_model = pipeline.aggregator_model.clone();
+        config.stages = pipeline.stages.clone();
+        config.critique = pipeline.critique.clone();
+        config.pipeline_prompts_dir = pipeline.prompts_dir.clone();
+        config
+    }
+
+    /// Directories searched for prompt templates, most specific first
+    pub fn prompt_dirs(&self) -> Vec<&str> {
+        self.pipeline_prompts_dir
+            .iter()
+            .chain(std::iter::once(&self.prompts_dir))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The stages to run, falling back to the reasoner → crafter flow
+    pub fn pipeline_stages(&self) -> Vec<Stage> {
+        if !self.stages.is_empty() {
+            return self.stages.clone();
+        }
+
+        vec![
+            Stage {
+                name: "reasoning".to_string(),
+                model: self.reasoning_model.clone(),
+                prompt: None,
+                inputs: Vec::new(),
+                ensemble: self.reasoning_ensemble.clone(),
+                aggregator: self.aggregator_model.clone(),
+            },
+            Stage {
+                name: "response".to_string(),
+                model: self.craft_model.clone(),
+                prompt: None,
+                inputs: vec!["reasoning".to_string()],
+                ensemble: Vec::new(),
+                aggregator: None,
+            },
+        ]
+    }
+}
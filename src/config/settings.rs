@@ -2,6 +2,15 @@
 
 use dotenv::dotenv;
 use std::env;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use crate::config::cache::{CacheBackend, CacheConfig};
+use crate::config::file::ConfigFile;
+use crate::config::pipelines::{CritiqueConfig, Pipeline, Stage};
+use crate::core::adaptive::{ReasoningBudget, ReasoningEffort, ReasoningMode};
+use crate::core::classifier::{ClassifierConfig, TaskClassifier};
+use crate::core::prompts::DEFAULT_PROMPTS_DIR;
 
 #[derive(Clone)]
 pub struct Config {
@@ -10,16 +19,62 @@ pub struct Config {
     pub temperature: f32,
     pub api_url: String,
     pub api_key: String,
+    /// Address the API server binds to
+    pub host: String,
+    /// Port the API server listens on
+    pub port: u16,
+    /// Listen on this Unix domain socket instead of TCP
+    pub unix_socket: Option<String>,
+    /// PEM certificate for serving the API over TLS
+    pub tls_cert: Option<String>,
+    /// PEM private key for serving the API over TLS
+    pub tls_key: Option<String>,
+    /// Path to the JSON configuration file
+    pub config_file: Option<String>,
+    /// Pipelines selectable via the request `model`
+    pub pipelines: Vec<Pipeline>,
+    /// Pipeline the terminal interface runs
+    pub pipeline: Option<String>,
+    /// Stages of the active pipeline; empty means the default two-phase flow
+    pub stages: Vec<Stage>,
+    /// Critique and refinement pass for the active pipeline
+    pub critique: Option<CritiqueConfig>,
+    /// Extra reasoning models run concurrently with `reasoning_model`
+    pub reasoning_ensemble: Vec<String>,
+    /// Model that synthesises the ensemble's reasoning
+    pub aggregator_model: Option<String>,
+    /// How requests are classified to pick category-specific prompts
+    pub classifier: ClassifierConfig,
+    /// Directory of prompt template files
+    pub prompts_dir: String,
+    /// Prompt directory of the active pipeline, searched before `prompts_dir`
+    pub pipeline_prompts_dir: Option<String>,
+    /// Response cache for the API server; `None` disables it
+    pub cache: Option<CacheConfig>,
+    /// How much reasoning to run per turn
+    pub reasoning_mode: ReasoningMode,
+    /// Effort and hard limits for the reasoning stages
+    pub reasoning_budget: ReasoningBudget,
+    /// Print terminal answers as raw text instead of rendered Markdown
+    pub plain: bool,
+    /// Terminal input history file; `None` disables history
+    pub history_file: Option<String>,
+    /// Directory terminal sessions are saved to; `None` disables saving
+    pub sessions_dir: Option<String>,
+    /// Save every upstream exchange to this directory
+    pub record_dir: Option<String>,
+    /// Serve upstream exchanges recorded in this directory instead of calling the network
+    pub replay_dir: Option<String>,
 }
 
 impl Config {
     pub fn new() -> Result<Self, String> {
         // First try to load from the current working directory
-        if let Ok(_) = dotenv::from_filename("./.env") {
+        if dotenv::from_filename("./.env").is_ok() {
             println!("Loaded configuration from ./.env file");
         } else {
             // Then try to load from the executable directory
-            if let Ok(_) = dotenv::dotenv() {
+            if dotenv::dotenv().is_ok() {
                 println!("Loaded configuration from default .env file");
             } else {
                 println!("No .env file found, using default or command-line configuration");
@@ -38,27 +93,74 @@ impl Config {
         let api_url =
             env::var("API_URL").unwrap_or_else(|_| String::from("http://localhost:1234"));
         let api_key = env::var("R_API_KEY").unwrap_or_else(|_| String::from(""));
+        let host = env::var("HOST").unwrap_or_else(|_| String::from("127.0.0.1"));
+        let port = port_from_env()?;
+        let unix_socket = env::var("UNIX_SOCKET").ok();
+        let tls_cert = env::var("TLS_CERT").ok();
+        let tls_key = env::var("TLS_KEY").ok();
+        let config_file = env::var("CONFIG_FILE").ok();
+        let pipeline = env::var("PIPELINE").ok();
+        let critique = critique_from_env();
+        let reasoning_ensemble = env::var("REASONING_ENSEMBLE")
+            .map(|models| parse_model_list(&models))
+            .unwrap_or_default();
+        let aggregator_model = env::var("AGGREGATOR_MODEL").ok();
+        let prompts_dir = env::var("PROMPTS_DIR").unwrap_or_else(|_| String::from(DEFAULT_PROMPTS_DIR));
+        let cache = env::var("RESPONSE_CACHE").ok().and_then(|backend| parse_cache(&backend));
+        let reasoning_mode = reasoning_mode_from_env()?;
+        let reasoning_budget = reasoning_budget_from_env()?;
+        let plain = plain_from_env();
+        let history_file = history_file_from_env();
+        let sessions_dir = sessions_dir_from_env();
+        let record_dir = env::var("RECORD_DIR").ok().filter(|d| !d.is_empty());
+        let replay_dir = env::var("REPLAY_DIR").ok().filter(|d| !d.is_empty());
 
-        // Check if API key is set
-        if api_key.is_empty() {
+        // Check if API key is set (replayed traffic needs none)
+        if api_key.is_empty() && replay_dir.is_none() {
             return Err("R_API_KEY environment variable must be set in .env file or provided via command line".to_string());
         }
 
-        Ok(Self {
+        let mut config = Self {
             reasoning_model,
             craft_model,
             temperature,
             api_url,
             api_key,
-        })
+            host,
+            port,
+            unix_socket,
+            tls_cert,
+            tls_key,
+            config_file,
+            pipelines: Vec::new(),
+            pipeline,
+            stages: Vec::new(),
+            critique,
+            reasoning_ensemble,
+            aggregator_model,
+            classifier: ClassifierConfig::default(),
+            prompts_dir,
+            pipeline_prompts_dir: None,
+            cache,
+            reasoning_mode,
+            reasoning_budget,
+            plain,
+            history_file,
+            sessions_dir,
+            record_dir,
+            replay_dir,
+        };
+        config.load_file()?;
+
+        Ok(config)
     }
 
-    pub fn from_args() -> Self {
+    pub fn from_args() -> Result<Self, String> {
         // Load environment variables from .env file
         dotenv().ok();
 
         // Parse command line arguments
-        let args: Vec<String> = env::args().collect();
+        let args = cli_args();
 
         // Default values
         let mut reasoning_model = env::var("REASONING_MODEL")
@@ -72,6 +174,27 @@ impl Config {
         let mut api_url =
             env::var("API_URL").unwrap_or_else(|_| String::from("http://localhost:1234"));
         let mut api_key = env::var("R_API_KEY").unwrap_or_else(|_| String::from(""));
+        let mut host = env::var("HOST").unwrap_or_else(|_| String::from("127.0.0.1"));
+        let mut port = port_from_env()?;
+        let mut unix_socket = env::var("UNIX_SOCKET").ok();
+        let mut tls_cert = env::var("TLS_CERT").ok();
+        let mut tls_key = env::var("TLS_KEY").ok();
+        let mut config_file = env::var("CONFIG_FILE").ok();
+        let mut pipeline = env::var("PIPELINE").ok();
+        let mut critique = critique_from_env();
+        let mut reasoning_ensemble = env::var("REASONING_ENSEMBLE")
+            .map(|models| parse_model_list(&models))
+            .unwrap_or_default();
+        let mut aggregator_model = env::var("AGGREGATOR_MODEL").ok();
+        let mut prompts_dir = env::var("PROMPTS_DIR").unwrap_or_else(|_| String::from(DEFAULT_PROMPTS_DIR));
+        let mut cache = env::var("RESPONSE_CACHE").ok().and_then(|backend| parse_cache(&backend));
+        let mut reasoning_mode = reasoning_mode_from_env()?;
+        let mut reasoning_budget = reasoning_budget_from_env()?;
+        let mut plain = plain_from_env();
+        let mut history_file = history_file_from_env();
+        let mut sessions_dir = sessions_dir_from_env();
+        let mut record_dir = env::var("RECORD_DIR").ok().filter(|d| !d.is_empty());
+        let mut replay_dir = env::var("REPLAY_DIR").ok().filter(|d| !d.is_empty());
 
         // Process each argument (command line args override env vars)
         for arg in args.iter() {
@@ -87,15 +210,285 @@ impl Config {
                 api_url = url.to_string();
             } else if let Some(key) = arg.strip_prefix("--api_key=") {
                 api_key = key.to_string();
+            } else if let Some(h) = arg.strip_prefix("--host=") {
+                host = h.to_string();
+            } else if let Some(p) = arg.strip_prefix("--port=") {
+                port = parse_port(p)?;
+            } else if let Some(path) = arg.strip_prefix("--unix_socket=") {
+                unix_socket = Some(path.to_string());
+            } else if let Some(path) = arg.strip_prefix("--tls_cert=") {
+                tls_cert = Some(path.to_string());
+            } else if let Some(path) = arg.strip_prefix("--tls_key=") {
+                tls_key = Some(path.to_string());
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                config_file = Some(path.to_string());
+            } else if let Some(id) = arg.strip_prefix("--pipeline=") {
+                pipeline = Some(id.to_string());
+            } else if let Some(rounds) = arg.strip_prefix("--critique_rounds=") {
+                if let Ok(max_rounds) = rounds.parse::<u32>() {
+                    critique = (max_rounds > 0).then(|| CritiqueConfig {
+                        model: critique.as_ref().and_then(|c| c.model.clone()),
+                        max_rounds,
+                    });
+                }
+            } else if let Some(models) = arg.strip_prefix("--reasoning_ensemble=") {
+                reasoning_ensemble = parse_model_list(models);
+            } else if let Some(model) = arg.strip_prefix("--aggregator_model=") {
+                aggregator_model = Some(model.to_string());
+            } else if let Some(dir) = arg.strip_prefix("--prompts_dir=") {
+                prompts_dir = dir.to_string();
+            } else if let Some(backend) = arg.strip_prefix("--cache=") {
+                cache = parse_cache(backend);
+            } else if let Some(mode) = arg.strip_prefix("--reasoning_mode=") {
+                reasoning_mode = parse_reasoning_mode(mode)?;
+            } else if let Some(effort) = arg.strip_prefix("--reasoning_effort=") {
+                reasoning_budget.effort = Some(parse_reasoning_effort(effort)?);
+            } else if let Some(tokens) = arg.strip_prefix("--reasoning_max_tokens=") {
+                reasoning_budget.max_tokens = Some(parse_limit(tokens, "reasoning token limit")?);
+            } else if let Some(seconds) = arg.strip_prefix("--reasoning_timeout=") {
+                reasoning_budget.max_seconds = Some(parse_limit(seconds, "reasoning timeout")?);
+            } else if arg == "--plain" {
+                plain = true;
+            } else if let Some(path) = arg.strip_prefix("--history_file=") {
+                history_file = Some(path.to_string()).filter(|p| !p.is_empty());
+            } else if let Some(dir) = arg.strip_prefix("--sessions_dir=") {
+                sessions_dir = Some(dir.to_string()).filter(|d| !d.is_empty());
+            } else if let Some(dir) = arg.strip_prefix("--record=") {
+                record_dir = Some(dir.to_string()).filter(|d| !d.is_empty());
+            } else if let Some(dir) = arg.strip_prefix("--replay=") {
+                replay_dir = Some(dir.to_string()).filter(|d| !d.is_empty());
+            } else if let Some(model) = arg.strip_prefix("--critique_model=") {
+                critique = Some(CritiqueConfig {
+                    model: Some(model.to_string()),
+                    max_rounds: critique.as_ref().map_or(1, |c| c.max_rounds),
+                });
             }
         }
 
-        Self {
+        Ok(Self {
             reasoning_model,
             craft_model,
             temperature,
             api_url,
             api_key,
+            host,
+            port,
+            unix_socket,
+            tls_cert,
+            tls_key,
+            config_file,
+            pipelines: Vec::new(),
+            pipeline,
+            stages: Vec::new(),
+            critique,
+            reasoning_ensemble,
+            aggregator_model,
+            classifier: ClassifierConfig::default(),
+            prompts_dir,
+            pipeline_prompts_dir: None,
+            cache,
+            reasoning_mode,
+            reasoning_budget,
+            plain,
+            history_file,
+            sessions_dir,
+            record_dir,
+            replay_dir,
+        })
+    }
+
+    /// Load the JSON configuration file and register its pipelines
+    pub fn load_file(&mut self) -> Result<(), String> {
+        let file = ConfigFile::load(self.config_file.as_deref())?;
+        for pipeline in &file.pipelines {
+            pipeline.validate()?;
+        }
+        TaskClassifier::new(&file.classifier)?;
+        self.pipelines = file.pipelines;
+        self.classifier = file.classifier;
+        if file.cache.is_some() {
+            self.cache = file.cache;
+        }
+
+        // Keep the default pipeline available unless the file overrides it
+        if self.find_pipeline(crate::config::pipelines::DEFAULT_PIPELINE_ID).is_none() {
+            self.pipelines.insert(0, Pipeline::default_for(self));
         }
+
+        Ok(())
+    }
+}
+
+/// Parse a port number, rejecting anything that is not one
+pub fn parse_port(value: &str) -> Result<u16, String> {
+    value
+        .trim()
+        .parse::<u16>()
+        .map_err(|_| format!("Invalid port {:?}: expected a number from 0 to 65535", value))
+}
+
+/// `PORT`, or 3000 when it is not set
+fn port_from_env() -> Result<u16, String> {
+    match env::var("PORT") {
+        Ok(port) => parse_port(&port),
+        Err(_) => Ok(3000),
+    }
+}
+
+/// Command line arguments, with `--record <dir>` and `--replay <dir>` joined
+/// into the `--record=<dir>` form the other options use
+pub fn cli_args() -> Vec<String> {
+    let mut args = Vec::new();
+    let mut iter = env::args();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--record" | "--replay" => match iter.next() {
+                Some(dir) => args.push(format!("{}={}", arg, dir)),
+                None => args.push(arg),
+            },
+            _ => args.push(arg),
+        }
+    }
+    args
+}
+
+/// Parse a `--cache` backend name; `off` or anything unknown disables the cache
+fn parse_cache(backend: &str) -> Option<CacheConfig> {
+    let backend = match backend.trim().to_lowercase().as_str() {
+        "memory" => CacheBackend::Memory,
+        "disk" => CacheBackend::Disk,
+        _ => return None,
+    };
+    Some(CacheConfig {
+        backend,
+        ..CacheConfig::default()
+    })
+}
+
+/// Parse a reasoning mode name, rejecting unknown modes
+fn parse_reasoning_mode(value: &str) -> Result<ReasoningMode, String> {
+    ReasoningMode::parse(value).ok_or_else(|| {
+        format!("Invalid reasoning mode {:?}: expected auto, full, brief or skip", value)
+    })
+}
+
+/// Parse a reasoning effort name, rejecting unknown efforts
+fn parse_reasoning_effort(value: &str) -> Result<ReasoningEffort, String> {
+    ReasoningEffort::parse(value)
+        .ok_or_else(|| format!("Invalid reasoning effort {:?}: expected low, medium or high", value))
+}
+
+/// Parse a numeric limit such as a token budget or timeout
+fn parse_limit<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| format!("Invalid {} {:?}: expected a whole number", name, value))
+}
+
+/// `REASONING_MODE`, or the default mode when it is not set
+fn reasoning_mode_from_env() -> Result<ReasoningMode, String> {
+    match env::var("REASONING_MODE") {
+        Ok(mode) => parse_reasoning_mode(&mode),
+        Err(_) => Ok(ReasoningMode::default()),
+    }
+}
+
+/// Read the default reasoning budget from `REASONING_EFFORT`,
+/// `REASONING_MAX_TOKENS` and `REASONING_TIMEOUT_SECS`
+fn reasoning_budget_from_env() -> Result<ReasoningBudget, String> {
+    Ok(ReasoningBudget {
+        effort: env::var("REASONING_EFFORT")
+            .ok()
+            .map(|effort| parse_reasoning_effort(&effort))
+            .transpose()?,
+        max_tokens: env::var("REASONING_MAX_TOKENS")
+            .ok()
+            .map(|t| parse_limit(&t, "reasoning token limit"))
+            .transpose()?,
+        max_seconds: env::var("REASONING_TIMEOUT_SECS")
+            .ok()
+            .map(|t| parse_limit(&t, "reasoning timeout"))
+            .transpose()?,
+    })
+}
+
+/// Directory for data kept across runs, such as the input history
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("dualmind"))
+}
+
+/// Read the history file from `HISTORY_FILE`; an empty value disables history
+fn history_file_from_env() -> Option<String> {
+    match env::var("HISTORY_FILE") {
+        Ok(path) => Some(path).filter(|p| !p.is_empty()),
+        Err(_) => data_dir().map(|dir| dir.join("history.txt").to_string_lossy().into_owned()),
     }
-} 
\ No newline at end of file
+}
+
+/// Read the sessions directory from `SESSIONS_DIR`; an empty value disables saving
+fn sessions_dir_from_env() -> Option<String> {
+    match env::var("SESSIONS_DIR") {
+        Ok(dir) => Some(dir).filter(|d| !d.is_empty()),
+        Err(_) => data_dir().map(|dir| dir.join("sessions").to_string_lossy().into_owned()),
+    }
+}
+
+/// Plain output is used when `PLAIN` is set or stdout is not a terminal
+fn plain_from_env() -> bool {
+    env::var("PLAIN").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        || !std::io::stdout().is_terminal()
+}
+
+/// Read the default critique settings from `CRITIQUE_ROUNDS` and `CRITIQUE_MODEL`
+fn critique_from_env() -> Option<CritiqueConfig> {
+    let max_rounds = env::var("CRITIQUE_ROUNDS")
+        .ok()
+        .and_then(|r| r.parse::<u32>().ok())
+        .unwrap_or(0);
+    let model = env::var("CRITIQUE_MODEL").ok();
+
+    if max_rounds == 0 && model.is_none() {
+        return None;
+    }
+
+    Some(CritiqueConfig {
+        model,
+        max_rounds: max_rounds.max(1),
+    })
+}
+
+/// Parse a comma-separated list of model names
+fn parse_model_list(models: &str) -> Vec<String> {
+    models
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_must_be_numbers() {
+        assert_eq!(parse_port("8080"), Ok(8080));
+        assert_eq!(parse_port(" 3000 "), Ok(3000));
+        assert!(parse_port("abc").is_err());
+        assert!(parse_port("70000").is_err());
+        assert!(parse_port("").is_err());
+    }
+
+    #[test]
+    fn reasoning_settings_must_be_valid() {
+        assert_eq!(parse_reasoning_mode(" Brief "), Ok(ReasoningMode::Brief));
+        assert!(parse_reasoning_mode("fast").is_err());
+        assert_eq!(parse_reasoning_effort("high"), Ok(ReasoningEffort::High));
+        assert!(parse_reasoning_effort("max").is_err());
+        assert_eq!(parse_limit::<u32>("2048", "reasoning token limit"), Ok(2048));
+        assert!(parse_limit::<u32>("-1", "reasoning token limit").is_err());
+        assert!(parse_limit::<u64>("30s", "reasoning timeout").is_err());
+    }
+}
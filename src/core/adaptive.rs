@@ -0,0 +1,251 @@
+//! Per-turn policy deciding how much reasoning a request gets
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::core::classifier::TaskCategory;
+use crate::core::llm::{is_continuation_request, CompletionLimits};
+
+/// Messages with at most this many words count as short
+const SHORT_MESSAGE_WORDS: usize = 12;
+
+/// Token limit for the reasoning stages of a brief turn
+const BRIEF_REASONING_MAX_TOKENS: u32 = 512;
+
+/// Instruction appended to reasoning prompts on a brief turn or at low effort
+const BRIEF_REASONING_INSTRUCTION: &str =
+    "Keep your reasoning brief: a few short bullet points covering only what the answer needs.";
+
+/// Instruction appended to reasoning prompts at high effort
+const THOROUGH_REASONING_INSTRUCTION: &str =
+    "Reason thoroughly: consider alternative approaches, check each step, and note any assumptions before concluding.";
+
+/// How much reasoning to run before the final stage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningMode {
+    /// Let the policy decide per turn
+    #[default]
+    Auto,
+    Full,
+    /// Run the reasoning stages with a reduced budget
+    Brief,
+    /// Skip the reasoning stages and reuse the previous turn's reasoning
+    Skip,
+}
+
+impl ReasoningMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningMode::Auto => "auto",
+            ReasoningMode::Full => "full",
+            ReasoningMode::Brief => "brief",
+            ReasoningMode::Skip => "skip",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "auto" => Some(ReasoningMode::Auto),
+            "full" => Some(ReasoningMode::Full),
+            "brief" => Some(ReasoningMode::Brief),
+            "skip" => Some(ReasoningMode::Skip),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ReasoningMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The reasoning mode chosen for a turn and why
+#[derive(Debug, Clone)]
+pub struct ReasoningDecision {
+    /// `Full`, `Brief` or `Skip`, never `Auto`
+    pub mode: ReasoningMode,
+    pub reason: &'static str,
+}
+
+/// Decide how much reasoning the latest message needs
+///
+/// An explicit `mode` always wins. In `auto` mode small talk skips the
+/// reasoner, follow-ups to a previous turn reuse its reasoning (or get a
+/// brief pass for coding and math), and short non-technical messages get a
+/// reduced budget.
+pub fn decide_reasoning(
+    mode: ReasoningMode,
+    category: TaskCategory,
+    message: &str,
+    has_previous_reasoning: bool,
+) -> ReasoningDecision {
+    if mode != ReasoningMode::Auto {
+        return ReasoningDecision {
+            mode,
+            reason: "requested",
+        };
+    }
+
+    let technical = matches!(category, TaskCategory::Coding | TaskCategory::Math);
+    let (mode, reason) = if category == TaskCategory::ChitChat {
+        (ReasoningMode::Skip, "small talk")
+    } else if has_previous_reasoning && is_continuation_request(message) {
+        if technical {
+            (ReasoningMode::Brief, "follow-up to a technical turn")
+        } else {
+            (ReasoningMode::Skip, "follow-up to the previous turn")
+        }
+    } else if !technical
+        && category != TaskCategory::Analysis
+        && message.split_whitespace().count() <= SHORT_MESSAGE_WORDS
+    {
+        (ReasoningMode::Brief, "short message")
+    } else {
+        (ReasoningMode::Full, "full reasoning")
+    };
+
+    ReasoningDecision { mode, reason }
+}
+
+/// Reasoning effort requested by the client with `reasoning_effort`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "low" => Some(ReasoningEffort::Low),
+            "medium" => Some(ReasoningEffort::Medium),
+            "high" => Some(ReasoningEffort::High),
+            _ => None,
+        }
+    }
+
+    /// Reasoning token budget used when no explicit limit is given
+    fn default_max_tokens(&self) -> Option<u32> {
+        match self {
+            ReasoningEffort::Low => Some(1024),
+            ReasoningEffort::Medium => Some(4096),
+            ReasoningEffort::High => None,
+        }
+    }
+}
+
+/// Limits on the reasoning stages of a turn
+#[derive(Debug, Clone, Default)]
+pub struct ReasoningBudget {
+    pub effort: Option<ReasoningEffort>,
+    /// Hard token limit; the reasoner stream is cut off once it is reached
+    pub max_tokens: Option<u32>,
+    /// Hard time limit; the reasoner stream is cut off once it is reached
+    pub max_seconds: Option<u64>,
+}
+
+impl ReasoningBudget {
+    /// Limits for reasoning calls made in `mode`
+    pub fn limits(&self, mode: ReasoningMode) -> CompletionLimits {
+        let mut max_tokens = self
+            .max_tokens
+            .or_else(|| self.effort.and_then(|e| e.default_max_tokens()));
+        if mode == ReasoningMode::Brief {
+            max_tokens = Some(max_tokens.map_or(BRIEF_REASONING_MAX_TOKENS, |m| m.min(BRIEF_REASONING_MAX_TOKENS)));
+        }
+
+        CompletionLimits {
+            max_tokens,
+            reasoning_effort: self.effort,
+            max_duration: self.max_seconds.map(Duration::from_secs),
+        }
+    }
+
+    /// Extra instruction for the reasoning prompt, if any
+    pub fn instruction(&self, mode: ReasoningMode) -> Option<&'static str> {
+        match (mode, self.effort) {
+            (ReasoningMode::Brief, _) | (_, Some(ReasoningEffort::Low)) => Some(BRIEF_REASONING_INSTRUCTION),
+            (_, Some(ReasoningEffort::High)) => Some(THOROUGH_REASONING_INSTRUCTION),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auto(category: TaskCategory, message: &str, has_previous_reasoning: bool) -> ReasoningMode {
+        decide_reasoning(ReasoningMode::Auto, category, message, has_previous_reasoning).mode
+    }
+
+    #[test]
+    fn explicit_modes_win() {
+        let decision = decide_reasoning(ReasoningMode::Full, TaskCategory::ChitChat, "hi", false);
+        assert_eq!(decision.mode, ReasoningMode::Full);
+        assert_eq!(decision.reason, "requested");
+    }
+
+    #[test]
+    fn small_talk_skips() {
+        assert_eq!(auto(TaskCategory::ChitChat, "hello!", false), ReasoningMode::Skip);
+    }
+
+    #[test]
+    fn continuations_reuse_the_previous_reasoning() {
+        assert_eq!(auto(TaskCategory::General, "Please go on", true), ReasoningMode::Skip);
+        assert_eq!(auto(TaskCategory::Writing, "Tell me more about the second point", true), ReasoningMode::Skip);
+        assert_eq!(auto(TaskCategory::Coding, "ok, what's next?", true), ReasoningMode::Brief);
+        // Without earlier reasoning there is nothing to continue from
+        assert_eq!(auto(TaskCategory::Analysis, "continue", false), ReasoningMode::Full);
+    }
+
+    #[test]
+    fn continuation_phrases_match_whole_words() {
+        assert!(is_continuation_request("Continue."));
+        assert!(is_continuation_request("can you elaborate on that"));
+        assert!(!is_continuation_request("Moreover, the nextjs docs say otherwise"));
+        assert!(!is_continuation_request("I also need the furthest point"));
+        assert!(!is_continuation_request("What's the continuity equation?"));
+    }
+
+    #[test]
+    fn short_messages_are_not_continuations() {
+        assert!(!is_continuation_request("Write a haiku"));
+        assert_eq!(auto(TaskCategory::Coding, "Fix this bug", true), ReasoningMode::Full);
+        assert_eq!(auto(TaskCategory::Writing, "Write a haiku", true), ReasoningMode::Brief);
+    }
+
+    #[test]
+    fn short_non_technical_messages_are_brief() {
+        assert_eq!(auto(TaskCategory::General, "Capital of France?", false), ReasoningMode::Brief);
+        assert_eq!(auto(TaskCategory::Analysis, "Compare A and B", false), ReasoningMode::Full);
+        assert_eq!(auto(TaskCategory::Math, "What is 2 + 2?", false), ReasoningMode::Full);
+    }
+
+    #[test]
+    fn brief_turns_cap_the_reasoning_budget() {
+        let budget = ReasoningBudget {
+            max_tokens: Some(2000),
+            ..Default::default()
+        };
+        assert_eq!(budget.limits(ReasoningMode::Full).max_tokens, Some(2000));
+        assert_eq!(budget.limits(ReasoningMode::Brief).max_tokens, Some(BRIEF_REASONING_MAX_TOKENS));
+        assert_eq!(
+            ReasoningBudget::default().limits(ReasoningMode::Brief).max_tokens,
+            Some(BRIEF_REASONING_MAX_TOKENS)
+        );
+    }
+}
@@ -0,0 +1,431 @@
+//! Task classification used to pick category-specific prompts
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::core::llm::{stream_chat_completion, to_api_messages};
+use crate::models::Message;
+
+/// Kind of task a user message asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskCategory {
+    Coding,
+    Math,
+    Writing,
+    Analysis,
+    ChitChat,
+    General,
+}
+
+impl TaskCategory {
+    pub const ALL: [TaskCategory; 6] = [
+        TaskCategory::Coding,
+        TaskCategory::Math,
+        TaskCategory::Writing,
+        TaskCategory::Analysis,
+        TaskCategory::ChitChat,
+        TaskCategory::General,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskCategory::Coding => "coding",
+            TaskCategory::Math => "math",
+            TaskCategory::Writing => "writing",
+            TaskCategory::Analysis => "analysis",
+            TaskCategory::ChitChat => "chit_chat",
+            TaskCategory::General => "general",
+        }
+    }
+
+    /// Parse a category name, accepting `chit-chat` and `chitchat` spellings
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase().replace(['-', ' '], "_");
+        match name.as_str() {
+            "chitchat" => Some(TaskCategory::ChitChat),
+            _ => Self::ALL.into_iter().find(|c| c.as_str() == name),
+        }
+    }
+}
+
+impl std::fmt::Display for TaskCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A pattern that adds `weight` to a category's score when it matches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassifierRule {
+    pub category: TaskCategory,
+    /// Word or phrase matched case-insensitively on word boundaries
+    pub pattern: String,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// Treat `pattern` as a raw regular expression instead of a word
+    #[serde(default)]
+    pub regex: bool,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// How messages are classified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassifierMode {
+    #[default]
+    Rules,
+    Llm,
+}
+
+/// Classifier settings from the `classifier` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassifierConfig {
+    pub mode: ClassifierMode,
+    /// Model used in `llm` mode; defaults to the crafting model
+    pub model: Option<String>,
+    /// Minimum score for a category to win over `general`
+    pub threshold: f32,
+    /// Rules added to (or replacing) the built-in ones
+    pub rules: Vec<ClassifierRule>,
+    pub replace_default_rules: bool,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        Self {
+            mode: ClassifierMode::Rules,
+            model: None,
+            threshold: 1.0,
+            rules: Vec::new(),
+            replace_default_rules: false,
+        }
+    }
+}
+
+/// Rule-based classifier with an optional LLM classifier in front of it
+pub struct TaskClassifier {
+    rules: Vec<(ClassifierRule, Regex)>,
+    threshold: f32,
+    mode: ClassifierMode,
+    model: Option<String>,
+}
+
+impl TaskClassifier {
+    /// Build the classifier described by the configuration
+    pub fn new(config: &ClassifierConfig) -> Result<Self, String> {
+        let mut rules = if config.replace_default_rules {
+            Vec::new()
+        } else {
+            default_rules()
+        };
+        rules.extend(config.rules.iter().cloned());
+
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let regex = compile_rule(&rule)
+                    .map_err(|e| format!("Invalid classifier pattern {:?}: {}", rule.pattern, e))?;
+                Ok((rule, regex))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            rules,
+            threshold: config.threshold,
+            mode: config.mode,
+            model: config.model.clone(),
+        })
+    }
+
+    /// The classifier for `config`, built once and reused by later calls
+    pub fn shared(config: &ClassifierConfig) -> Result<Arc<Self>, String> {
+        static CLASSIFIERS: LazyLock<Mutex<HashMap<String, Arc<TaskClassifier>>>> =
+            LazyLock::new(Default::default);
+
+        let key = serde_json::to_string(config).map_err(|e| e.to_string())?;
+        let mut classifiers = CLASSIFIERS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(classifier) = classifiers.get(&key) {
+            return Ok(classifier.clone());
+        }
+        let classifier = Arc::new(Self::new(config)?);
+        classifiers.insert(key, classifier.clone());
+        Ok(classifier)
+    }
+
+    /// Score every category for `content` using the rules
+    pub fn scores(&self, content: &str) -> Vec<(TaskCategory, f32)> {
+        let mut scores: Vec<(TaskCategory, f32)> = Vec::new();
+        for (rule, regex) in &self.rules {
+            if regex.is_match(content) {
+                match scores.iter_mut().find(|(c, _)| *c == rule.category) {
+                    Some((_, score)) => *score += rule.weight,
+                    None => scores.push((rule.category, rule.weight)),
+                }
+            }
+        }
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores
+    }
+
+    /// Classify `content` using only the rules
+    ///
+    /// Chit-chat only wins when no other category scores at all, so a
+    /// greeting in front of a real question does not make it small talk.
+    pub fn classify_rules(&self, content: &str) -> TaskCategory {
+        match self.scores(content).as_slice() {
+            [(TaskCategory::ChitChat, score)] if *score >= self.threshold => TaskCategory::ChitChat,
+            scores => scores
+                .iter()
+                .find(|(category, score)| {
+                    !matches!(category, TaskCategory::ChitChat | TaskCategory::General) && *score >= self.threshold
+                })
+                .map_or(TaskCategory::General, |(category, _)| *category),
+        }
+    }
+
+    /// Classify the last user message, asking the LLM when configured
+    ///
+    /// Falls back to the rules when the LLM call fails or gives an unknown answer.
+    pub async fn classify(&self, client: &Client, config: &Config, content: &str) -> TaskCategory {
+        if self.mode == ClassifierMode::Llm {
+            let model = self.model.as_deref().unwrap_or(&config.craft_model);
+            match classify_with_llm(client, config, model, content).await {
+                Ok(Some(category)) => return category,
+                Ok(None) => eprintln!("Classifier: unrecognised answer from {}, using rules", model),
+                Err(e) => eprintln!("Classifier: {} failed ({}), using rules", model, e),
+            }
+        }
+        self.classify_rules(content)
+    }
+}
+
+/// Classify `content` with the built-in rules
+pub fn classify_task(content: &str) -> TaskCategory {
+    TaskClassifier::shared(&ClassifierConfig::default())
+        .map(|classifier| classifier.classify_rules(content))
+        .unwrap_or(TaskCategory::General)
+}
+
+/// Ask a model which category a message belongs to
+async fn classify_with_llm(
+    client: &Client,
+    config: &Config,
+    model: &str,
+    content: &str,
+) -> Result<Option<TaskCategory>, Box<dyn std::error::Error + Send + Sync>> {
+    let categories: Vec<&str> = TaskCategory::ALL.iter().map(|c| c.as_str()).collect();
+    let system_content = format!(
+        "Classify the user's message into exactly one of these categories: {}. Reply with only the category name.",
+        categories.join(", ")
+    );
+    let messages = [Message {
+        role: crate::models::Role::User,
+        content: content.to_string(),
+    }];
+    let api_messages = to_api_messages(&system_content, &messages);
+    let answer = stream_chat_completion(client, &config.api_key, config, model, &api_messages, |_| {}).await?;
+
+    Ok(TaskCategory::parse(answer.trim().trim_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '-')))
+}
+
+/// Compile a rule into a case-insensitive regex
+fn compile_rule(rule: &ClassifierRule) -> Result<Regex, regex::Error> {
+    if rule.regex {
+        return Regex::new(&format!("(?i){}", rule.pattern));
+    }
+
+    // Only anchor on word boundaries at ends that are word characters,
+    // so patterns like "c++" or "```" still match
+    let escaped = regex::escape(&rule.pattern);
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(rule.pattern.chars().next()) { r"\b" } else { "" };
+    let end = if is_word(rule.pattern.chars().last()) { r"\b" } else { "" };
+    Regex::new(&format!("(?i){}{}{}", start, escaped, end))
+}
+
+/// The built-in rule set
+fn default_rules() -> Vec<ClassifierRule> {
+    let word = |category, pattern: &str, weight| ClassifierRule {
+        category,
+        pattern: pattern.to_string(),
+        weight,
+        regex: false,
+    };
+    let regex = |category, pattern: &str, weight| ClassifierRule {
+        category,
+        pattern: pattern.to_string(),
+        weight,
+        regex: true,
+    };
+
+    let mut rules = Vec::new();
+
+    // Coding: strong signals are code fences and language or tooling names
+    rules.push(regex(TaskCategory::Coding, r"```", 3.0));
+    rules.push(regex(
+        TaskCategory::Coding,
+        r"(^|\n)\s*(def |fn |class \w+[:({]|import \w|#include|public static|function\s*\w*\()",
+        3.0,
+    ));
+    for pattern in [
+        "python", "javascript", "typescript", "rust", "golang", "java", "c++", "c#", "kotlin",
+        "swift", "ruby", "php", "sql", "html", "css", "bash script", "shell script", "regex",
+        "compile", "compiler", "stack trace", "segfault", "refactor", "unit test", "api endpoint",
+    ] {
+        rules.push(word(TaskCategory::Coding, pattern, 2.0));
+    }
+    for pattern in [
+        "code", "function", "script", "algorithm", "implement", "debug", "bug", "syntax",
+        "framework", "database",
+    ] {
+        rules.push(word(TaskCategory::Coding, pattern, 1.0));
+    }
+
+    // Math
+    rules.push(regex(TaskCategory::Math, r"\d+\s*[-+*/^=]\s*\d+", 2.0));
+    for pattern in [
+        "equation", "integral", "derivative", "theorem", "proof", "prove", "probability",
+        "matrix", "calculate", "solve for", "algebra", "geometry", "calculus", "statistics",
+    ] {
+        rules.push(word(TaskCategory::Math, pattern, 2.0));
+    }
+    for pattern in ["solve", "sum", "average", "percent", "percentage", "formula"] {
+        rules.push(word(TaskCategory::Math, pattern, 1.0));
+    }
+
+    // Writing
+    for pattern in [
+        "essay", "poem", "story", "cover letter", "blog post", "proofread", "rewrite", "rephrase",
+        "paraphrase", "draft an email", "write an email", "speech", "lyrics", "tagline",
+    ] {
+        rules.push(word(TaskCategory::Writing, pattern, 2.0));
+    }
+    for pattern in ["email", "letter", "paragraph", "tone", "grammar"] {
+        rules.push(word(TaskCategory::Writing, pattern, 1.0));
+    }
+
+    // Analysis
+    for pattern in [
+        "analyze", "analyse", "analysis", "compare", "comparison", "pros and cons", "trade-offs",
+        "tradeoffs", "evaluate", "assess", "summarize", "summarise", "root cause",
+    ] {
+        rules.push(word(TaskCategory::Analysis, pattern, 2.0));
+    }
+    for pattern in ["why", "explain", "difference between", "impact", "review"] {
+        rules.push(word(TaskCategory::Analysis, pattern, 1.0));
+    }
+
+    // General: questions about the world, which keep a greeting in front of
+    // them from counting as small talk
+    rules.push(regex(
+        TaskCategory::General,
+        r"\b(how|why) (do|does|did|can|could|would|should|is|was|many|much)\b|\b(what|who|where|when|which) (is|are|was|were|do|does|did)\b",
+        1.0,
+    ));
+
+    // Chit-chat; only wins when no other category scores (see `classify_rules`)
+    for pattern in [
+        "hi", "hello", "hey", "thanks", "thank you", "how are you", "good morning",
+        "good night", "bye", "lol", "nice", "cool",
+    ] {
+        rules.push(word(TaskCategory::ChitChat, pattern, 1.0));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(content: &str) -> TaskCategory {
+        TaskClassifier::new(&ClassifierConfig::default()).unwrap().classify_rules(content)
+    }
+
+    #[test]
+    fn words_only_match_on_boundaries() {
+        // "hi" inside "where", "sum" inside "assume", "code" inside "decode"
+        // used to be enough to pick a category
+        assert_eq!(classify("where did you go from here?"), TaskCategory::General);
+        assert_eq!(classify("I assume the weather is fine"), TaskCategory::General);
+        assert_eq!(classify("hi there"), TaskCategory::ChitChat);
+    }
+
+    #[test]
+    fn chit_chat_only_wins_alone() {
+        assert_eq!(classify("thanks, that was nice"), TaskCategory::ChitChat);
+        assert_eq!(classify("Hey, how do black holes form?"), TaskCategory::General);
+        // Two greetings outscore a single analysis word, but do not win over it
+        assert_eq!(classify("hi hello, why?"), TaskCategory::Analysis);
+    }
+
+    #[test]
+    fn picks_the_highest_scoring_category() {
+        assert_eq!(classify("Write a python function that parses JSON"), TaskCategory::Coding);
+        assert_eq!(classify("Prove the theorem and solve for x"), TaskCategory::Math);
+        assert_eq!(classify("Write a poem about autumn"), TaskCategory::Writing);
+        assert_eq!(classify("Compare the pros and cons of both plans"), TaskCategory::Analysis);
+    }
+
+    #[test]
+    fn symbols_match_without_word_boundaries() {
+        assert_eq!(classify("is c++ faster?"), TaskCategory::Coding);
+        assert_eq!(classify("```\nlet x = 1;\n```"), TaskCategory::Coding);
+    }
+
+    #[test]
+    fn custom_rules_and_threshold() {
+        let config = ClassifierConfig {
+            threshold: 2.0,
+            rules: vec![ClassifierRule {
+                category: TaskCategory::Writing,
+                pattern: "haiku".to_string(),
+                weight: 2.0,
+                regex: false,
+            }],
+            ..Default::default()
+        };
+        let classifier = TaskClassifier::new(&config).unwrap();
+        assert_eq!(classifier.classify_rules("a haiku please"), TaskCategory::Writing);
+        // A single 1.0 match stays below the threshold
+        assert_eq!(classifier.classify_rules("hello"), TaskCategory::General);
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let config = ClassifierConfig {
+            rules: vec![ClassifierRule {
+                category: TaskCategory::Coding,
+                pattern: "(".to_string(),
+                weight: 1.0,
+                regex: true,
+            }],
+            ..Default::default()
+        };
+        assert!(TaskClassifier::new(&config).is_err());
+    }
+
+    #[test]
+    fn shared_classifiers_are_reused_per_config() {
+        let config = ClassifierConfig::default();
+        let first = TaskClassifier::shared(&config).unwrap();
+        let second = TaskClassifier::shared(&config).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let other = ClassifierConfig {
+            threshold: 5.0,
+            ..Default::default()
+        };
+        assert!(!Arc::ptr_eq(&first, &TaskClassifier::shared(&other).unwrap()));
+    }
+}
@@ -1,6 +1,11 @@
 //! Core application logic
 
+pub mod adaptive;
+pub mod classifier;
 pub mod llm;
+pub mod pipeline;
+pub mod prompts;
+pub mod recording;
 mod processor;
 mod types;
 
@@ -0,0 +1,441 @@
+//! Multi-stage pipeline engine
+
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::Config;
+use crate::config::pipelines::Stage;
+use crate::core::adaptive::{decide_reasoning, ReasoningMode};
+use crate::core::classifier::{TaskCategory, TaskClassifier};
+use crate::core::llm::{
+    stream_chat_completion, stream_chat_completion_with_limits, to_api_messages, wrap_reasoning,
+    CompletionLimits, StreamedCompletion,
+};
+use crate::core::prompts::{system_prompt, Prompt, PromptKind, PromptVars};
+use crate::error::Error;
+use crate::models::openai::ChatMessage;
+use crate::models::{Message, Role};
+
+/// Progress reported while a pipeline runs
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    /// The request was classified before the first stage ran
+    TaskClassified { category: TaskCategory },
+    /// The adaptive reasoning policy chose how much reasoning to run
+    ReasoningDecided { mode: ReasoningMode, reason: String },
+    /// A stage hit its reasoning budget and was stopped early
+    StageCutOff { index: usize },
+    StageStarted { index: usize, name: String, model: String },
+    Content { index: usize, content: String },
+    StageFinished { index: usize },
+    /// The final answer is being checked by the critique model
+    CritiqueStarted { round: u32, model: String },
+    CritiqueFinished { round: u32, critique: CritiqueRound },
+    /// One model of an ensemble stage finished, successfully or not
+    EnsembleMemberFinished { index: usize, model: String, error: Option<String> },
+}
+
+/// One round of the critique pass
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CritiqueRound {
+    pub round: u32,
+    pub model: String,
+    pub approved: bool,
+    pub content: String,
+}
+
+/// Output of a single stage
+#[derive(Debug, Clone)]
+pub struct StageOutput {
+    pub name: String,
+    pub model: String,
+    pub content: String,
+}
+
+/// Outputs of every stage of a completed pipeline run
+#[derive(Debug, Clone)]
+pub struct PipelineOutput {
+    pub stages: Vec<StageOutput>,
+    /// Critique trail, empty when the critique pass is disabled
+    pub critiques: Vec<CritiqueRound>,
+    /// How much reasoning this run used
+    pub reasoning_mode: ReasoningMode,
+}
+
+impl PipelineOutput {
+    /// The final answer, produced by the last stage
+    pub fn answer(&self) -> &str {
+        self.stages.last().map_or("", |s| s.content.as_str())
+    }
+
+    /// Outputs of every stage before the last
+    pub fn reasoning_stages(&self) -> &[StageOutput] {
+        &self.stages[..self.stages.len().saturating_sub(1)]
+    }
+
+    /// Everything the earlier stages produced, wrapped in think tags
+    pub fn reasoning(&self) -> String {
+        let earlier: Vec<&StageOutput> = self.reasoning_stages().iter().collect();
+        wrap_reasoning(&join_outputs(&earlier))
+    }
+}
+
+/// Run the stages of the active pipeline over the session
+///
+/// Events are sent to `events` as each stage streams; the sender is dropped
+/// when the run finishes so receivers can simply drain the channel.
+/// `previous_reasoning` holds the earlier stages of the previous turn, reused
+/// when the adaptive policy skips reasoning.
+pub async fn run_pipeline(
+    client: &Client,
+    config: &Config,
+    session_messages: &[Message],
+    previous_reasoning: &[StageOutput],
+    events: Option<UnboundedSender<PipelineEvent>>,
+) -> Result<PipelineOutput, Error> {
+    let stages = config.pipeline_stages();
+
+    let send = |event: PipelineEvent| {
+        if let Some(events) = &events {
+            let _ = events.send(event);
+        }
+    };
+
+    let classifier = TaskClassifier::shared(&config.classifier)?;
+    let last_user = session_messages
+        .iter()
+        .rfind(|m| m.role == Role::User)
+        .map_or("", |m| m.content.as_str());
+    let category = classifier.classify(client, config, last_user).await;
+    send(PipelineEvent::TaskClassified { category });
+    let vars = PromptVars::new(category, session_messages);
+
+    let decision = decide_reasoning(config.reasoning_mode, category, last_user, !previous_reasoning.is_empty());
+    send(PipelineEvent::ReasoningDecided {
+        mode: decision.mode,
+        reason: decision.reason.to_string(),
+    });
+    let limits = config.reasoning_budget.limits(decision.mode);
+    let instruction = config.reasoning_budget.instruction(decision.mode);
+
+    let mut outputs: Vec<StageOutput> = Vec::new();
+    for (index, stage) in stages.iter().enumerate() {
+        let is_reasoning = index + 1 < stages.len();
+
+        // Skipped stages reuse what the same stage produced last turn
+        if is_reasoning && decision.mode == ReasoningMode::Skip {
+            let content = previous_reasoning
+                .iter()
+                .find(|o| o.name == stage.name)
+                .map(|o| o.content.clone())
+                .unwrap_or_default();
+            outputs.push(StageOutput {
+                name: stage.name.clone(),
+                model: stage.model.clone(),
+                content,
+            });
+            continue;
+        }
+
+        send(PipelineEvent::StageStarted {
+            index,
+            name: stage.name.clone(),
+            model: stage.models().join(", "),
+        });
+
+        let mut prompt = stage_system_prompt(config, stage, &outputs, &vars);
+        if is_reasoning && let Some(instruction) = instruction {
+            prompt.text = format!("{}\n\n{}", prompt.text, instruction);
+        }
+        let api_messages = to_api_messages(&prompt.text, &prompt.session_messages(session_messages));
+        let stage_limits = if is_reasoning { limits } else { Default::default() };
+        let content = if stage.ensemble.is_empty() {
+            stream_chat_completion_with_limits(
+                client,
+                &config.api_key,
+                config,
+                &stage.model,
+                &api_messages,
+                &stage_limits,
+                |content| {
+                    send(PipelineEvent::Content {
+                        index,
+                        content: content.to_string(),
+                    })
+                },
+            )
+            .await
+        } else {
+            run_ensemble(client, config, stage, index, &api_messages, &stage_limits, &send).await
+        }
+        .map(|completion| {
+            if completion.cut_off {
+                send(PipelineEvent::StageCutOff { index });
+            }
+            completion.content
+        })
+        .map_err(|e| e.context(format!("Error in {} stage", stage.name)))?;
+
+        send(PipelineEvent::StageFinished { index });
+        outputs.push(StageOutput {
+            name: stage.name.clone(),
+            model: stage.model.clone(),
+            content: content.trim().to_string(),
+        });
+    }
+
+    let mut critiques = Vec::new();
+    if let Some(critique_config) = &config.critique {
+        let critique_model = critique_config
+            .model
+            .clone()
+            .unwrap_or_else(|| config.reasoning_model.clone());
+        let final_index = stages.len() - 1;
+        let final_stage = &stages[final_index];
+        let reasoning = join_outputs(&outputs[..final_index].iter().collect::<Vec<_>>());
+
+        for round in 1..=critique_config.max_rounds {
+            let answer = outputs[final_index].content.clone();
+            send(PipelineEvent::CritiqueStarted {
+                round,
+                model: critique_model.clone(),
+            });
+
+            let api_messages = to_api_messages(
+                &critique_system_prompt(&reasoning, &answer),
+                session_messages,
+            );
+            let content = stream_chat_completion(
+                client,
+                &config.api_key,
+                config,
+                &critique_model,
+                &api_messages,
+                |_| {},
+            )
+            .await
+            .map_err(|e| e.context(format!("Error in critique round {}", round)))?;
+
+            let critique = CritiqueRound {
+                round,
+                model: critique_model.clone(),
+                approved: is_approved(&content),
+                content: content.trim().to_string(),
+            };
+            send(PipelineEvent::CritiqueFinished {
+                round,
+                critique: critique.clone(),
+            });
+            let approved = critique.approved;
+            critiques.push(critique);
+            if approved {
+                break;
+            }
+
+            // Re-run the final stage with the critique attached
+            send(PipelineEvent::StageStarted {
+                index: final_index,
+                name: final_stage.name.clone(),
+                model: final_stage.model.clone(),
+            });
+            let mut prompt = stage_system_prompt(config, final_stage, &outputs[..final_index], &vars);
+            prompt.text = format!(
+                "{}\n\n{}",
+                prompt.text,
+                refinement_instructions(&answer, &critiques[critiques.len() - 1].content)
+            );
+            let api_messages = to_api_messages(&prompt.text, &prompt.session_messages(session_messages));
+            let refined = stream_chat_completion(
+                client,
+                &config.api_key,
+                config,
+                &final_stage.model,
+                &api_messages,
+                |content| {
+                    send(PipelineEvent::Content {
+                        index: final_index,
+                        content: content.to_string(),
+                    })
+                },
+            )
+            .await
+            .map_err(|e| e.context(format!("Error refining {} stage", final_stage.name)))?;
+            send(PipelineEvent::StageFinished { index: final_index });
+
+            outputs[final_index].content = refined.trim().to_string();
+        }
+    }
+
+    Ok(PipelineOutput {
+        stages: outputs,
+        critiques,
+        reasoning_mode: decision.mode,
+    })
+}
+
+/// Run every model of an ensemble stage concurrently and merge their outputs
+///
+/// Members that fail are reported and skipped; the stage only fails when
+/// none of them succeed.
+async fn run_ensemble(
+    client: &Client,
+    config: &Config,
+    stage: &Stage,
+    index: usize,
+    api_messages: &[ChatMessage],
+    limits: &CompletionLimits,
+    send: &(impl Fn(PipelineEvent) + Sync),
+) -> Result<StreamedCompletion, Error> {
+    let models = stage.models();
+    let calls = models.iter().map(|model| async move {
+        let result =
+            stream_chat_completion_with_limits(client, &config.api_key, config, model, api_messages, limits, |_| {}).await;
+        send(PipelineEvent::EnsembleMemberFinished {
+            index,
+            model: model.clone(),
+            error: result.as_ref().err().map(Error::to_string),
+        });
+        result.map(|completion| {
+            let output = StageOutput {
+                name: model.clone(),
+                model: model.clone(),
+                content: completion.content.trim().to_string(),
+            };
+            (output, completion.cut_off)
+        })
+    });
+
+    let (succeeded, failed): (Vec<_>, Vec<_>) = join_all(calls).await.into_iter().partition(Result::is_ok);
+    let (outputs, cut_offs): (Vec<StageOutput>, Vec<bool>) = succeeded.into_iter().filter_map(Result::ok).unzip();
+    let cut_off = cut_offs.contains(&true);
+    if outputs.is_empty() {
+        // Report every failure, classified like the first one
+        let errors: Vec<Error> = failed.into_iter().filter_map(Result::err).collect();
+        let messages: Vec<String> = errors.iter().map(Error::to_string).collect();
+        let first = errors.into_iter().next().unwrap_or_else(|| Error::internal("no ensemble models"));
+        return Err(first.with_message(messages.join("; ")).context("all ensemble models failed"));
+    }
+
+    let merged = merge_ensemble_outputs(&outputs);
+    let emit = |content: &str| {
+        send(PipelineEvent::Content {
+            index,
+            content: content.to_string(),
+        })
+    };
+
+    // A single surviving member or no aggregator means plain concatenation
+    let aggregator = match &stage.aggregator {
+        Some(aggregator) if outputs.len() > 1 => aggregator,
+        _ => {
+            emit(&merged);
+            return Ok(StreamedCompletion { content: merged, cut_off });
+        }
+    };
+
+    // Same conversation as the members, with the aggregator's system prompt
+    let mut aggregator_messages = api_messages.to_vec();
+    aggregator_messages[0] = ChatMessage::new(Role::System, aggregator_system_prompt(&merged));
+    match stream_chat_completion(client, &config.api_key, config, aggregator, &aggregator_messages, emit).await {
+        Ok(synthesised) => Ok(StreamedCompletion { content: synthesised, cut_off }),
+        Err(e) => {
+            // Fall back to the attributed outputs rather than failing the request
+            send(PipelineEvent::EnsembleMemberFinished {
+                index,
+                model: aggregator.clone(),
+                error: Some(e.to_string()),
+            });
+            emit(&merged);
+            Ok(StreamedCompletion { content: merged, cut_off })
+        }
+    }
+}
+
+/// Concatenate ensemble outputs with attribution
+fn merge_ensemble_outputs(outputs: &[StageOutput]) -> String {
+    if let [single] = outputs {
+        return single.content.clone();
+    }
+
+    outputs
+        .iter()
+        .map(|o| format!("### Reasoning from {}\n\n{}", o.model, o.content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// System prompt asking the aggregator to synthesise several plans into one
+fn aggregator_system_prompt(merged: &str) -> String {
+    format!(
+        "You are combining the reasoning of several models about the user's last request. \
+Compare their plans, keep the points they agree on, resolve disagreements by picking the best-supported option, and \
+write a single coherent step-by-step plan. Do not answer the request yourself.\n\n{merged}"
+    )
+}
+
+/// Reply the critique model gives when the answer needs no changes
+const APPROVED_MARKER: &str = "APPROVED";
+
+/// System prompt asking the critique model to check an answer
+fn critique_system_prompt(reasoning: &str, answer: &str) -> String {
+    format!(
+        "You are a meticulous reviewer. Check whether the answer below fully addresses the user's last request and follows every part of the reasoning plan. \
+If it does, reply with only the word {APPROVED_MARKER}. Otherwise list each problem or omission as a short bullet point, without rewriting the answer.\n\n\
+Reasoning plan:\n{reasoning}\n\nAnswer to review:\n{answer}"
+    )
+}
+
+/// Extra instructions for the final stage when it is re-invoked with a critique
+fn refinement_instructions(answer: &str, critique: &str) -> String {
+    format!(
+        "Your previous answer was reviewed and the reviewer found these problems:\n{critique}\n\n\
+Previous answer:\n{answer}\n\n\
+Write a complete, corrected answer that addresses every problem. Do not mention the review."
+    )
+}
+
+/// Whether a critique approves the answer as is
+fn is_approved(critique: &str) -> bool {
+    critique
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .eq_ignore_ascii_case(APPROVED_MARKER)
+}
+
+/// Build the system prompt for a stage from its template and inputs
+fn stage_system_prompt(config: &Config, stage: &Stage, outputs: &[StageOutput], vars: &PromptVars) -> Prompt {
+    let inputs: Vec<&StageOutput> = outputs
+        .iter()
+        .filter(|o| stage.inputs.contains(&o.name))
+        .collect();
+
+    // Without a template, stages that build on earlier output act as the crafter
+    match &stage.prompt {
+        Some(template) => {
+            let stage_outputs: Vec<(&str, &str)> =
+                inputs.iter().map(|o| (o.name.as_str(), o.content.as_str())).collect();
+            Prompt::render_with(template, &vars.with_reasoning(&join_outputs(&inputs)), &stage_outputs)
+        }
+        None if inputs.is_empty() => system_prompt(config, PromptKind::Reasoner, vars),
+        None => system_prompt(
+            config,
+            PromptKind::Crafter,
+            &vars.with_reasoning(&wrap_reasoning(&join_outputs(&inputs))),
+        ),
+    }
+}
+
+/// Join stage outputs, labelling each one when there is more than one
+fn join_outputs(outputs: &[&StageOutput]) -> String {
+    match outputs {
+        [] => String::new(),
+        [single] => single.content.clone(),
+        _ => outputs
+            .iter()
+            .map(|o| format!("## {}\n\n{}", o.name, o.content))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    }
+}
@@ -0,0 +1,314 @@
+//! Prompt templates loaded from disk with built-in defaults
+//!
+//! Templates are looked up in the active pipeline's `prompts_dir`, then the
+//! global one, as `<kind>.<task type>.md` and then `<kind>.md`. Files are
+//! re-read whenever their modification time changes, so edits take effect on
+//! the next request without a restart.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::core::classifier::TaskCategory;
+use crate::models::{Message, Role};
+
+/// Default directory searched for prompt templates
+pub const DEFAULT_PROMPTS_DIR: &str = "./prompts";
+
+/// Placeholder for the system prompt the client sent with the request
+pub const USER_SYSTEM_PROMPT_VAR: &str = "{{user_system_prompt}}";
+
+/// Which phase a prompt is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    Reasoner,
+    Crafter,
+}
+
+impl PromptKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptKind::Reasoner => "reasoner",
+            PromptKind::Crafter => "crafter",
+        }
+    }
+}
+
+/// Values substituted into prompt templates
+#[derive(Debug, Clone)]
+pub struct PromptVars {
+    pub reasoning: String,
+    pub task_type: TaskCategory,
+    pub user_system_prompt: String,
+}
+
+impl PromptVars {
+    /// Variables for a request, taking the user system prompt from the session
+    pub fn new(task_type: TaskCategory, session_messages: &[Message]) -> Self {
+        let user_system_prompt = session_messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        Self {
+            reasoning: String::new(),
+            task_type,
+            user_system_prompt,
+        }
+    }
+
+    /// The same variables with `reasoning` set
+    pub fn with_reasoning(&self, reasoning: &str) -> Self {
+        Self {
+            reasoning: reasoning.to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A rendered system prompt
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub text: String,
+    /// The template placed the client's system prompt itself, so the
+    /// client's system messages should not be forwarded separately
+    pub includes_user_system_prompt: bool,
+}
+
+impl Prompt {
+    /// Render `template` with `vars`
+    pub fn render(template: &str, vars: &PromptVars) -> Self {
+        Self::render_with(template, vars, &[])
+    }
+
+    /// Render `template` with `vars` and extra named values such as stage outputs
+    ///
+    /// Placeholders are replaced in a single pass, so a `{{...}}` that appears
+    /// inside a substituted value is left as it is. Unknown placeholders are
+    /// kept, and `extra` values take precedence over the built-in variables.
+    pub fn render_with(template: &str, vars: &PromptVars, extra: &[(&str, &str)]) -> Self {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let lookup = |name: &str| -> Option<&str> {
+            if let Some((_, value)) = extra.iter().find(|(n, _)| *n == name) {
+                return Some(value);
+            }
+            match name {
+                "reasoning" => Some(&vars.reasoning),
+                "task_type" => Some(vars.task_type.as_str()),
+                "date" => Some(&date),
+                "user_system_prompt" => Some(&vars.user_system_prompt),
+                _ => None,
+            }
+        };
+
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            text.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            match after
+                .find("}}")
+                .and_then(|end| lookup(&after[..end]).map(|value| (end, value)))
+            {
+                Some((end, value)) => {
+                    text.push_str(value);
+                    rest = &after[end + 2..];
+                }
+                None => {
+                    text.push_str("{{");
+                    rest = after;
+                }
+            }
+        }
+        text.push_str(rest);
+
+        Self {
+            text,
+            includes_user_system_prompt: template.contains(USER_SYSTEM_PROMPT_VAR),
+        }
+    }
+
+    /// Session messages to send alongside this prompt
+    pub fn session_messages(&self, session_messages: &[Message]) -> Vec<Message> {
+        session_messages
+            .iter()
+            .filter(|m| !(self.includes_user_system_prompt && m.role == Role::System))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Load and render the system prompt of `kind` for the configured directories
+pub fn system_prompt(config: &Config, kind: PromptKind, vars: &PromptVars) -> Prompt {
+    Prompt::render(&load_template(&config.prompt_dirs(), kind, vars.task_type), vars)
+}
+
+/// Find the most specific template for `kind` and `category`
+pub fn load_template(dirs: &[&str], kind: PromptKind, category: TaskCategory) -> String {
+    for dir in dirs {
+        for file_name in [
+            format!("{}.{}.md", kind.as_str(), category.as_str()),
+            format!("{}.md", kind.as_str()),
+        ] {
+            if let Some(template) = read_template(&Path::new(dir).join(file_name)) {
+                return template;
+            }
+        }
+    }
+
+    builtin_template(kind, category).to_string()
+}
+
+/// A template file and the modification time it was read at
+struct CachedTemplate {
+    modified: SystemTime,
+    contents: String,
+}
+
+static TEMPLATE_CACHE: LazyLock<Mutex<HashMap<PathBuf, CachedTemplate>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Read a template file, re-reading it only when it has changed
+fn read_template(path: &Path) -> Option<String> {
+    let mut cache = TEMPLATE_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+    let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => {
+            cache.remove(path);
+            return None;
+        }
+    };
+
+    if let Some(cached) = cache.get(path)
+        && cached.modified == modified
+    {
+        return Some(cached.contents.clone());
+    }
+
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let action = if cache.contains_key(path) { "Reloaded" } else { "Loaded" };
+            eprintln!("{} prompt template {}", action, path.display());
+            cache.insert(
+                path.to_path_buf(),
+                CachedTemplate {
+                    modified,
+                    contents: contents.clone(),
+                },
+            );
+            Some(contents)
+        }
+        Err(e) => {
+            eprintln!("Failed to read prompt template {}: {}", path.display(), e);
+            cache.remove(path);
+            None
+        }
+    }
+}
+
+/// The template used when no file overrides it
+///
+/// These are the files shipped in the repository's `prompts/` directory.
+fn builtin_template(kind: PromptKind, category: TaskCategory) -> &'static str {
+    match kind {
+        PromptKind::Reasoner => match category {
+            TaskCategory::Coding => include_str!("../../prompts/reasoner.coding.md"),
+            TaskCategory::Math => include_str!("../../prompts/reasoner.math.md"),
+            TaskCategory::Writing => include_str!("../../prompts/reasoner.writing.md"),
+            TaskCategory::Analysis => include_str!("../../prompts/reasoner.analysis.md"),
+            TaskCategory::ChitChat => include_str!("../../prompts/reasoner.chit_chat.md"),
+            TaskCategory::General => include_str!("../../prompts/reasoner.general.md"),
+        },
+        PromptKind::Crafter => match category {
+            TaskCategory::Coding => include_str!("../../prompts/crafter.coding.md"),
+            TaskCategory::Math => include_str!("../../prompts/crafter.math.md"),
+            TaskCategory::Writing => include_str!("../../prompts/crafter.writing.md"),
+            TaskCategory::Analysis => include_str!("../../prompts/crafter.analysis.md"),
+            TaskCategory::ChitChat => include_str!("../../prompts/crafter.chit_chat.md"),
+            TaskCategory::General => include_str!("../../prompts/crafter.general.md"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(reasoning: &str) -> PromptVars {
+        PromptVars {
+            reasoning: reasoning.to_string(),
+            task_type: TaskCategory::Coding,
+            user_system_prompt: "Be terse.".to_string(),
+        }
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let prompt = Prompt::render(
+            "{{task_type}}: {{reasoning}} / {{user_system_prompt}}",
+            &vars("plan"),
+        );
+        assert_eq!(prompt.text, "coding: plan / Be terse.");
+        assert!(prompt.includes_user_system_prompt);
+    }
+
+    #[test]
+    fn substituted_values_are_not_expanded_again() {
+        let prompt = Prompt::render(
+            "Reasoning: {{reasoning}}",
+            &vars("use {{date}} and {{user_system_prompt}}"),
+        );
+        assert_eq!(
+            prompt.text,
+            "Reasoning: use {{date}} and {{user_system_prompt}}"
+        );
+        assert!(!prompt.includes_user_system_prompt);
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders_are_kept() {
+        let prompt = Prompt::render("{{nope}} {{task_type}} {{reasoning", &vars(""));
+        assert_eq!(prompt.text, "{{nope}} coding {{reasoning");
+    }
+
+    #[test]
+    fn extra_values_take_precedence() {
+        let prompt = Prompt::render_with(
+            "{{draft}} | {{reasoning}}",
+            &vars("joined"),
+            &[("draft", "first {{reasoning}}"), ("reasoning", "override")],
+        );
+        assert_eq!(prompt.text, "first {{reasoning}} | override");
+    }
+
+    #[test]
+    fn files_override_the_builtin_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_name = dir.path().to_str().unwrap();
+        assert_eq!(
+            load_template(&[dir_name], PromptKind::Crafter, TaskCategory::Math),
+            builtin_template(PromptKind::Crafter, TaskCategory::Math)
+        );
+
+        std::fs::write(dir.path().join("crafter.md"), "generic").unwrap();
+        assert_eq!(
+            load_template(&[dir_name], PromptKind::Crafter, TaskCategory::Math),
+            "generic"
+        );
+
+        std::fs::write(dir.path().join("crafter.math.md"), "math").unwrap();
+        assert_eq!(
+            load_template(&[dir_name], PromptKind::Crafter, TaskCategory::Math),
+            "math"
+        );
+        assert_eq!(
+            load_template(&[dir_name], PromptKind::Reasoner, TaskCategory::Math),
+            builtin_template(PromptKind::Reasoner, TaskCategory::Math)
+        );
+    }
+}
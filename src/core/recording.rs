@@ -0,0 +1,292 @@
+//! Recording and replay of upstream LLM traffic
+//!
+//! With `--record <dir>` every upstream exchange is saved to `dir` as one JSON
+//! file: the request body, the response status and each chunk of the
+//! response with the time it arrived. With `--replay <dir>` those files are
+//! served instead of calling the network, matched by request body and paced
+//! as recorded, so a bug report can be reproduced without the provider.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::error::{Error, ErrorKind};
+
+/// One upstream request and what came back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub url: String,
+    pub request: Value,
+    /// `None` when no response arrived
+    pub status: Option<u16>,
+    /// Time until the response headers arrived
+    pub response_ms: u64,
+    pub chunks: Vec<RecordedChunk>,
+    /// Transport error that ended the exchange, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the caller stopped reading before the response was complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abandoned_ms: Option<u64>,
+}
+
+/// A piece of the response body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedChunk {
+    /// Time since the request was sent
+    pub at_ms: u64,
+    pub data: String,
+}
+
+/// How long a replayed stream stays open past the point it was abandoned,
+/// so the limit that stopped the recording stops the replay too
+const ABANDONED_GRACE: Duration = Duration::from_secs(1);
+
+/// Recordings served so far per request key, so repeated requests get the
+/// recordings in the order they were made
+static REPLAYED: LazyLock<Mutex<HashMap<String, usize>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static ANNOUNCE: Once = Once::new();
+
+enum Source {
+    Live(BoxStream<'static, reqwest::Result<String>>),
+    Replay {
+        chunks: VecDeque<RecordedChunk>,
+        error: Option<String>,
+        abandoned_ms: Option<u64>,
+    },
+}
+
+/// Response of an upstream call, live or replayed
+pub struct UpstreamResponse {
+    status: StatusCode,
+    source: Source,
+    started: Instant,
+    /// Exchange being recorded, saved once the response is finished or dropped
+    recording: Option<(PathBuf, Exchange)>,
+}
+
+/// POST `body` to `url`, recording or replaying the exchange as configured
+pub async fn send(
+    client: &Client,
+    config: &Config,
+    url: &str,
+    api_key: &str,
+    body: &impl Serialize,
+) -> Result<UpstreamResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let body = &serde_json::to_value(body)?;
+    if let Some(dir) = &config.replay_dir {
+        announce(&format!("Replaying upstream traffic from {}", dir));
+        return replay(Path::new(dir), body).await;
+    }
+
+    let started = Instant::now();
+    let result = client
+        .post(url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(body)
+        .send()
+        .await;
+
+    let recording = config.record_dir.as_ref().map(|dir| {
+        announce(&format!("Recording upstream traffic to {}", dir));
+        let exchange = Exchange {
+            url: url.to_string(),
+            request: body.clone(),
+            status: result.as_ref().ok().map(|r| r.status().as_u16()),
+            response_ms: started.elapsed().as_millis() as u64,
+            chunks: Vec::new(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            abandoned_ms: None,
+        };
+        (recording_path(Path::new(dir), &request_key(body)), exchange)
+    });
+
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            if let Some((path, exchange)) = &recording {
+                save(path, exchange);
+            }
+            return Err(Error::from(e).into());
+        }
+    };
+
+    Ok(UpstreamResponse {
+        status: response.status(),
+        source: Source::Live(
+            response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+                .boxed(),
+        ),
+        started,
+        recording,
+    })
+}
+
+impl UpstreamResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The next piece of the body, or `None` once it is complete
+    pub async fn next_chunk(&mut self) -> Option<Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+        let next = match &mut self.source {
+            Source::Live(stream) => stream.next().await.map(|chunk| chunk.map_err(|e| e.to_string())),
+            Source::Replay { chunks, error, abandoned_ms } => match chunks.pop_front() {
+                Some(chunk) => {
+                    let due = self.started + Duration::from_millis(chunk.at_ms);
+                    tokio::time::sleep_until(due.into()).await;
+                    Some(Ok(chunk.data))
+                }
+                None => match abandoned_ms.take() {
+                    Some(ms) => {
+                        let due = self.started + Duration::from_millis(ms) + ABANDONED_GRACE;
+                        tokio::time::sleep_until(due.into()).await;
+                        Some(Err(format!("The recorded stream was abandoned after {} ms", ms)))
+                    }
+                    None => error.take().map(Err),
+                },
+            },
+        };
+
+        match &next {
+            Some(Ok(data)) => {
+                if let Some((_, exchange)) = &mut self.recording {
+                    exchange.chunks.push(RecordedChunk {
+                        at_ms: self.started.elapsed().as_millis() as u64,
+                        data: data.clone(),
+                    });
+                }
+            }
+            Some(Err(e)) => {
+                if let Some((_, exchange)) = &mut self.recording {
+                    exchange.error = Some(e.clone());
+                }
+                self.finish_recording();
+            }
+            None => self.finish_recording(),
+        }
+        next.map(|result| result.map_err(|e| Error::new(ErrorKind::Upstream, e).into()))
+    }
+
+    /// The whole body as text
+    pub async fn text(mut self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut text = String::new();
+        while let Some(chunk) = self.next_chunk().await {
+            text.push_str(&chunk?);
+        }
+        Ok(text)
+    }
+
+    fn finish_recording(&mut self) {
+        if let Some((path, exchange)) = self.recording.take() {
+            save(&path, &exchange);
+        }
+    }
+}
+
+impl Drop for UpstreamResponse {
+    /// A response abandoned part way, such as a stream cut off at its budget,
+    /// is saved with the chunks read so far
+    fn drop(&mut self) {
+        if let Some((_, exchange)) = &mut self.recording {
+            exchange.abandoned_ms = Some(self.started.elapsed().as_millis() as u64);
+        }
+        self.finish_recording();
+    }
+}
+
+/// Serve the recording matching `body`
+async fn replay(dir: &Path, body: &Value) -> Result<UpstreamResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let key = request_key(body);
+    let nth = {
+        let mut replayed = REPLAYED.lock().unwrap();
+        let count = replayed.entry(format!("{}/{}", dir.display(), key)).or_default();
+        *count += 1;
+        *count
+    };
+
+    // Repeated requests get the next recording, or the last one once they run out
+    let path = (1..=nth)
+        .rev()
+        .map(|n| numbered_path(dir, &key, n))
+        .find(|path| path.exists())
+        .ok_or_else(|| format!("No recording in {} matches this request (key {})", dir.display(), key))?;
+    let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let exchange: Exchange =
+        serde_json::from_str(&json).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    let started = Instant::now();
+    tokio::time::sleep(Duration::from_millis(exchange.response_ms)).await;
+    let Some(status) = exchange.status else {
+        let message = exchange.error.unwrap_or_else(|| "Recorded request failed".to_string());
+        return Err(Error::new(ErrorKind::Upstream, message).into());
+    };
+
+    Ok(UpstreamResponse {
+        status: StatusCode::from_u16(status)?,
+        source: Source::Replay {
+            chunks: exchange.chunks.into(),
+            error: exchange.error,
+            abandoned_ms: exchange.abandoned_ms,
+        },
+        started,
+        recording: None,
+    })
+}
+
+/// Key recordings are matched by: a hash of the request body
+pub fn request_key(body: &Value) -> String {
+    let hash = format!("{:x}", Sha256::digest(body.to_string().as_bytes()));
+    hash[..16].to_string()
+}
+
+/// Claim the first free file name for a new recording of `key`
+///
+/// The file is created right away so concurrent identical requests get
+/// different names.
+fn recording_path(dir: &Path, key: &str) -> PathBuf {
+    let _ = std::fs::create_dir_all(dir);
+    (1..)
+        .map(|n| numbered_path(dir, key, n))
+        .find(|path| {
+            std::fs::OpenOptions::new().write(true).create_new(true).open(path).is_ok()
+                || !dir.is_dir()
+        })
+        .unwrap()
+}
+
+/// `<key>.json` for the first recording of a request, `<key>-<n>.json` after that
+fn numbered_path(dir: &Path, key: &str, n: usize) -> PathBuf {
+    if n == 1 {
+        dir.join(format!("{}.json", key))
+    } else {
+        dir.join(format!("{}-{}.json", key, n))
+    }
+}
+
+fn save(path: &Path, exchange: &Exchange) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(path, serde_json::to_string_pretty(exchange).unwrap_or_default()));
+    if let Err(e) = result {
+        eprintln!("Failed to save recording {}: {}", path.display(), e);
+    }
+}
+
+fn announce(message: &str) {
+    ANNOUNCE.call_once(|| eprintln!("{}", message));
+}
@@ -0,0 +1,258 @@
+//! Errors surfaced to API clients
+//!
+//! Failures of upstream providers keep the status and `error.code` they were
+//! sent with, and are classified so the API server can answer with the
+//! matching HTTP status and OpenAI `error.type`/`error.code` instead of a
+//! blanket 500.
+
+use std::fmt;
+
+use axum::http::StatusCode;
+use serde_json::Value;
+
+use crate::models::openai::{truncate, ErrorBody, ErrorResponse};
+
+/// What went wrong, as far as a client is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The upstream provider rejected our API key
+    UpstreamAuth,
+    /// The upstream provider is rate limiting us or the quota is used up
+    RateLimited,
+    /// The requested model or pipeline does not exist
+    ModelNotFound,
+    /// The conversation does not fit in the model's context window
+    ContextLengthExceeded,
+    /// The upstream provider did not answer in time
+    Timeout,
+    /// The request was rejected as malformed
+    InvalidRequest,
+    /// The upstream provider failed or sent something we could not use
+    Upstream,
+    Internal,
+}
+
+impl ErrorKind {
+    /// HTTP status the API server answers with
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::UpstreamAuth => StatusCode::UNAUTHORIZED,
+            ErrorKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorKind::ModelNotFound => StatusCode::NOT_FOUND,
+            ErrorKind::ContextLengthExceeded | ErrorKind::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorKind::Upstream => StatusCode::BAD_GATEWAY,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// OpenAI `error.type`
+    pub fn error_type(self) -> &'static str {
+        match self {
+            ErrorKind::UpstreamAuth => "authentication_error",
+            ErrorKind::RateLimited => "rate_limit_error",
+            ErrorKind::ModelNotFound | ErrorKind::ContextLengthExceeded | ErrorKind::InvalidRequest => {
+                "invalid_request_error"
+            }
+            ErrorKind::Timeout => "timeout_error",
+            ErrorKind::Upstream | ErrorKind::Internal => "api_error",
+        }
+    }
+
+    /// OpenAI `error.code` used when the upstream did not send one
+    pub fn default_code(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::UpstreamAuth => Some("invalid_api_key"),
+            ErrorKind::RateLimited => Some("rate_limit_exceeded"),
+            ErrorKind::ModelNotFound => Some("model_not_found"),
+            ErrorKind::ContextLengthExceeded => Some("context_length_exceeded"),
+            ErrorKind::Timeout => Some("timeout"),
+            ErrorKind::Upstream => Some("upstream_error"),
+            ErrorKind::InvalidRequest | ErrorKind::Internal => None,
+        }
+    }
+}
+
+/// A classified error, with what the upstream provider said when it came from one
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+    upstream_status: Option<u16>,
+    upstream_code: Option<Value>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            upstream_status: None,
+            upstream_code: None,
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// Classify a non-success upstream response from its status and body
+    pub fn from_upstream(status: u16, body: &str) -> Self {
+        let reason = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("");
+        match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(response) => {
+                let message = format!("API request failed: {} {} - {}", status, reason, response.error.message);
+                Self::from_error_body(Some(status), response.error).with_message(message)
+            }
+            Err(_) => Self {
+                kind: classify(Some(status), None, None, body),
+                message: format!("API request failed: {} {} - {}", status, reason, truncate(body)),
+                upstream_status: Some(status),
+                upstream_code: None,
+            },
+        }
+    }
+
+    /// Classify an `{"error": ...}` body, sent with `status` or inside a stream
+    pub fn from_error_body(status: Option<u16>, error: ErrorBody) -> Self {
+        Self {
+            kind: classify(status, error.kind.as_deref(), error.code.as_ref(), &error.message),
+            message: format!("Upstream error: {}", error.message),
+            upstream_status: status,
+            upstream_code: error.code,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// HTTP status the upstream provider answered with
+    pub fn upstream_status(&self) -> Option<u16> {
+        self.upstream_status
+    }
+
+    /// `error.code` the upstream provider sent
+    pub fn upstream_code(&self) -> Option<&Value> {
+        self.upstream_code.as_ref()
+    }
+
+    /// Replace the message, keeping the kind and upstream details
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Prefix the message with where the error happened
+    pub fn context(mut self, context: impl fmt::Display) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+
+    /// OpenAI `error.code`: the upstream's own code when it sent a textual one
+    pub fn code(&self) -> Option<String> {
+        match &self.upstream_code {
+            Some(Value::String(code)) if !code.is_empty() && code.parse::<u16>().is_err() => Some(code.clone()),
+            _ => self.kind.default_code().map(str::to_string),
+        }
+    }
+
+    /// The `{"error": ...}` body sent to API clients
+    pub fn to_response(&self) -> ErrorResponse {
+        let code = match self.code() {
+            Some(code) => Value::from(code),
+            None => Value::from(self.kind.status().as_u16()),
+        };
+        ErrorResponse {
+            error: ErrorBody {
+                message: self.message.clone(),
+                kind: Some(self.kind.error_type().to_string()),
+                code: Some(code),
+                extra: [("param".to_string(), Value::Null)].into_iter().collect(),
+            },
+        }
+    }
+}
+
+/// Work out the kind of an upstream error from whatever it told us
+fn classify(status: Option<u16>, error_type: Option<&str>, code: Option<&Value>, message: &str) -> ErrorKind {
+    let code = code.and_then(Value::as_str).unwrap_or_default();
+    let error_type = error_type.unwrap_or_default();
+    let message = message.to_lowercase();
+    let mentions = |needles: &[&str]| {
+        needles
+            .iter()
+            .any(|needle| code == *needle || error_type == *needle || message.contains(needle))
+    };
+
+    if status == Some(413)
+        || mentions(&[
+            "context_length_exceeded",
+            "context length",
+            "context window",
+            "maximum context",
+            "prompt is too long",
+        ])
+    {
+        return ErrorKind::ContextLengthExceeded;
+    }
+    match status {
+        Some(401 | 403) => ErrorKind::UpstreamAuth,
+        Some(429) => ErrorKind::RateLimited,
+        Some(404) => ErrorKind::ModelNotFound,
+        Some(408 | 504) => ErrorKind::Timeout,
+        _ if mentions(&["invalid_api_key", "authentication_error"]) => ErrorKind::UpstreamAuth,
+        _ if mentions(&["rate_limit_exceeded", "rate_limit_error", "insufficient_quota"]) => ErrorKind::RateLimited,
+        _ if mentions(&["model_not_found"]) => ErrorKind::ModelNotFound,
+        Some(400 | 422) => ErrorKind::InvalidRequest,
+        _ => ErrorKind::Upstream,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        let kind = if error.is_timeout() {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::Upstream
+        };
+        Self::new(kind, format!("Upstream request failed: {}", error))
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    /// Keep a boxed `Error` as it is; anything else is internal
+    fn from(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match error.downcast::<Error>() {
+            Ok(error) => *error,
+            Err(error) => Self::internal(error.to_string()),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::internal(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self::internal(message)
+    }
+}
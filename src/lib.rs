@@ -4,13 +4,14 @@ pub mod api;
 pub mod cli;
 pub mod config;
 pub mod core;
+pub mod error;
 pub mod middleware;
 pub mod models;
 pub mod streaming;
+pub mod testing;
 pub mod utils;
 
 use reqwest::Client;
-use std::env;
 
 /// Run the application with the given configuration
 #[tokio::main]
@@ -22,25 +23,43 @@ pub async fn run() -> Result<(), String> {
     let client = Client::new();
     
     // Choose mode based on command line arguments
-    let args: Vec<String> = env::args().collect();
+    let args = config::cli_args();
     if args.len() > 1 {
         match args[1].as_str() {
             "--api" => {
                 // Run API server
                 api::server::start(client, config).await.map_err(|e| e.to_string())?;
             }
+            "chat" => {
+                // Terminal chat, optionally resuming a saved session
+                let resume = cli::sessions::Resume::from_args(&args[2..]);
+                cli::terminal::start(client, config, resume).await.map_err(|e| e.to_string())?;
+            }
+            "sessions" => {
+                cli::sessions::run(&config, &args[2..]).map_err(|e| e.to_string())?;
+            }
+            "batch" => {
+                cli::batch::run(client, config, &args[2..]).await.map_err(|e| e.to_string())?;
+            }
+            "eval" => {
+                cli::eval::run(client, config, &args[2..]).await.map_err(|e| e.to_string())?;
+            }
+            "ask" => {
+                // Answer one question without the interactive loop
+                cli::ask::run(client, config, &args[2..]).await.map_err(|e| e.to_string())?;
+            }
             "--test-client" => {
                 // Run test client
                 api::client::test().await.map_err(|e| e.to_string())?;
             }
             _ => {
                 // Run terminal interface
-                cli::terminal::start(client, config).await.map_err(|e| e.to_string())?;
+                cli::terminal::start(client, config, cli::sessions::Resume::New).await.map_err(|e| e.to_string())?;
             }
         }
     } else {
         // Run terminal interface by default
-        cli::terminal::start(client, config).await.map_err(|e| e.to_string())?;
+        cli::terminal::start(client, config, cli::sessions::Resume::New).await.map_err(|e| e.to_string())?;
     }
 
     Ok(())
@@ -23,10 +23,10 @@ pub async fn log_request(req: Request, next: Next) -> Response {
         if let Ok(json) = serde_json::from_str::<Value>(&body_str) {
             // Redact sensitive information
             let mut json = json.clone();
-            if let Some(obj) = json.as_object_mut() {
-                if obj.contains_key("api_key") {
-                    obj.insert("api_key".to_string(), Value::String("*****".to_string()));
-                }
+            if let Some(obj) = json.as_object_mut()
+                && obj.contains_key("api_key")
+            {
+                obj.insert("api_key".to_string(), Value::String("*****".to_string()));
             }
             Some(json)
         } else {
@@ -77,7 +77,7 @@ pub async fn log_request(req: Request, next: Next) -> Response {
 
 /// Helper function to log headers while excluding sensitive information
 fn log_headers(headers: &HeaderMap) {
-    let sensitive_headers = vec!["authorization", "cookie", "x-api-key"];
+    let sensitive_headers = ["authorization", "cookie", "x-api-key"];
 
     for (key, value) in headers.iter() {
         let header_name = key.as_str().to_lowercase();
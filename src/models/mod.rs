@@ -1,5 +1,7 @@
 //! Domain models
 
+pub mod openai;
+
 use serde::{Deserialize, Serialize};
 use serde::de::{self, Deserializer};
 use serde_json::Value;
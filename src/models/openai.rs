@@ -0,0 +1,296 @@
+//! OpenAI chat completion wire format
+//!
+//! Used for requests sent to upstream providers, the responses and stream
+//! chunks they send back, and the responses the API server sends to its own
+//! clients. Fields that are not modelled here are kept in `extra` and passed
+//! through unchanged.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::{Error, ErrorKind};
+use crate::models::{Message, Role};
+
+/// A message in a chat completion request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            extra: Map::new(),
+        }
+    }
+}
+
+impl From<&Message> for ChatMessage {
+    fn from(message: &Message) -> Self {
+        Self::new(message.role.clone(), message.content.clone())
+    }
+}
+
+/// Body of `POST /v1/chat/completions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Reasoning effort in the OpenAI form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    /// Reasoning settings in the OpenRouter form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatRequest {
+    pub fn new(model: impl Into<String>, messages: Vec<ChatMessage>) -> Self {
+        Self {
+            model: model.into(),
+            messages,
+            temperature: None,
+            stream: None,
+            max_tokens: None,
+            reasoning_effort: None,
+            reasoning: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Token counts reported with a completion
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The assistant message of a completion, or the delta of a stream chunk
+///
+/// `content` is missing when the model refused or only called tools.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssistantMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Choice {
+    #[serde(default)]
+    pub index: u32,
+    pub message: AssistantMessage,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A non-streamed chat completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default = "completion_object")]
+    pub object: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub model: String,
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatResponse {
+    /// A single-choice completion with `content` as the answer
+    pub fn new(id: impl Into<String>, created: i64, model: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            object: completion_object(),
+            created,
+            model: model.into(),
+            choices: vec![Choice {
+                index: 0,
+                message: AssistantMessage {
+                    role: Some("assistant".to_string()),
+                    content: Some(content.into()),
+                    ..Default::default()
+                },
+                finish_reason: Some("stop".to_string()),
+                extra: Map::new(),
+            }],
+            usage: None,
+            extra: Map::new(),
+        }
+    }
+
+    /// Parse an upstream response body, failing on anything that is not a completion
+    pub fn parse(body: &str) -> Result<Self, Error> {
+        if let Ok(error) = serde_json::from_str::<ErrorResponse>(body) {
+            return Err(Error::from_error_body(None, error.error));
+        }
+        serde_json::from_str(body).map_err(|e| {
+            Error::new(
+                ErrorKind::Upstream,
+                format!("Unexpected upstream response ({}): {}", e, truncate(body)),
+            )
+        })
+    }
+
+    /// The answer of the first choice
+    pub fn into_content(self) -> Result<String, Error> {
+        let choice = self
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::Upstream, "Upstream response has no choices"))?;
+        match (choice.message.content, choice.message.refusal) {
+            (Some(content), _) => Ok(content),
+            (None, Some(refusal)) => Err(Error::new(ErrorKind::Upstream, format!("The model refused: {}", refusal))),
+            (None, None) => Err(Error::new(
+                ErrorKind::Upstream,
+                format!(
+                    "Upstream response has no content (finish_reason: {})",
+                    choice.finish_reason.as_deref().unwrap_or("none")
+                ),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkChoice {
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub delta: AssistantMessage,
+    /// Whole message sent by providers that ignore `stream`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<AssistantMessage>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// One `data:` event of a streamed chat completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatChunk {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default = "chunk_object")]
+    pub object: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<ChunkChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatChunk {
+    /// A single-choice chunk carrying `delta`
+    pub fn new(id: impl Into<String>, created: i64, model: impl Into<String>, delta: AssistantMessage) -> Self {
+        Self {
+            id: id.into(),
+            object: chunk_object(),
+            created,
+            model: model.into(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                message: None,
+                finish_reason: None,
+                extra: Map::new(),
+            }],
+            usage: None,
+            extra: Map::new(),
+        }
+    }
+
+    /// The content this chunk adds to the answer
+    ///
+    /// A refusal is reported as an error.
+    pub fn into_content(self) -> Result<Option<String>, Error> {
+        let Some(choice) = self.choices.into_iter().next() else {
+            // Anthropic-style `{"text": ...}` chunks
+            if let Some(text) = self.extra.get("text").and_then(Value::as_str) {
+                return Ok(Some(text.to_string()).filter(|t| !t.is_empty()));
+            }
+            // Usage-only and content-filter chunks carry no content
+            return Ok(None);
+        };
+        let message = choice.message.unwrap_or(choice.delta);
+        if let Some(refusal) = message.refusal.filter(|r| !r.is_empty()) {
+            return Err(Error::new(ErrorKind::Upstream, format!("The model refused: {}", refusal)));
+        }
+        Ok(message.content.filter(|c| !c.is_empty()))
+    }
+}
+
+/// `{"error": {...}}` as sent by providers and by the API server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub message: String,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn completion_object() -> String {
+    "chat.completion".to_string()
+}
+
+fn chunk_object() -> String {
+    "chat.completion.chunk".to_string()
+}
+
+/// Shorten an upstream body for an error message
+pub(crate) fn truncate(body: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    if body.chars().count() > MAX_CHARS {
+        format!("{}…", body.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        body.to_string()
+    }
+}
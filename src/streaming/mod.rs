@@ -1,57 +1,41 @@
 //! Streaming functionality
 
-/// Process a stream line and extract content
-pub fn process_stream(line: &str) -> Option<String> {
-    // Skip empty lines and OpenRouter processing messages
-    if line.is_empty() || line == "data: " || line == "[DONE]" || line.contains("OPENROUTER PROCESSING") {
-        return None;
-    }
+use crate::error::{Error, ErrorKind};
+use crate::models::openai::{truncate, ChatChunk, ErrorResponse};
 
-    // Check if the line starts with "data: "
-    let json_str = if line.starts_with("data: ") {
-        // Skip the "data: " prefix
-        &line[6..]
-    } else {
-        line
-    };
+/// A line of an upstream SSE stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamLine {
+    /// Answer content
+    Content(String),
+    /// Blank lines, comments, other SSE fields, `[DONE]` and chunks without content
+    Skip,
+}
 
-    // Try to parse as JSON
-    if let Ok(json) = serde_json::from_str::<serde_json::Value>(json_str) {
-        // Check for OpenAI format
-        if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-            if let Some(first_choice) = choices.first() {
-                // Check for delta content
-                if let Some(content) = first_choice
-                    .get("delta")
-                    .and_then(|d| d.get("content"))
-                    .and_then(|c| c.as_str())
-                {
-                    if !content.is_empty() {
-                        return Some(content.to_string());
-                    }
-                }
-                
-                // Check for message content
-                if let Some(content) = first_choice
-                    .get("message")
-                    .and_then(|m| m.get("content"))
-                    .and_then(|c| c.as_str())
-                {
-                    if !content.is_empty() {
-                        return Some(content.to_string());
-                    }
-                }
-            }
-        }
-        
-        // Check for Anthropic format
-        if let Some(text) = json.get("text").and_then(|t| t.as_str()) {
-            if !text.is_empty() {
-                return Some(text.to_string());
-            }
-        }
+/// Parse one line of an upstream stream
+///
+/// Errors sent in the stream, refusals and `data:` payloads that are not
+/// chat completion chunks are reported instead of being skipped.
+pub fn parse_stream_line(line: &str) -> Result<StreamLine, Error> {
+    let line = line.trim();
+    let Some(payload) = line.strip_prefix("data:").map(str::trim) else {
+        return Ok(StreamLine::Skip);
+    };
+    if payload.is_empty() || payload == "[DONE]" {
+        return Ok(StreamLine::Skip);
     }
 
-    // If we couldn't parse it as JSON or find the content, return None
-    None
-} 
\ No newline at end of file
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(payload) {
+        return Err(Error::from_error_body(None, error.error));
+    }
+    let chunk: ChatChunk = serde_json::from_str(payload).map_err(|e| {
+        Error::new(
+            ErrorKind::Upstream,
+            format!("Unexpected upstream stream chunk ({}): {}", e, truncate(payload)),
+        )
+    })?;
+    Ok(match chunk.into_content()? {
+        Some(content) => StreamLine::Content(content),
+        None => StreamLine::Skip,
+    })
+}
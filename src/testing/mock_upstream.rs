@@ -0,0 +1,391 @@
+//! A scriptable OpenAI-compatible upstream for tests
+//!
+//! `MockUpstream::start()` listens on an ephemeral local port and answers
+//! `POST /v1/chat/completions`, streamed or not. Responses are picked by the
+//! first matching rule; without one the last user message is echoed back.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Which requests a rule applies to
+#[derive(Debug, Clone)]
+pub enum Match {
+    Any,
+    /// Requests for this model
+    Model(String),
+    /// Requests with a message containing this text
+    Contains(String),
+}
+
+impl Match {
+    fn matches(&self, request: &RecordedRequest) -> bool {
+        match self {
+            Match::Any => true,
+            Match::Model(model) => request.model == *model,
+            Match::Contains(text) => request.messages().any(|(_, content)| content.contains(text.as_str())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Reply {
+    Text(String),
+    /// Echo the last user message, prefixed with the model name
+    Echo,
+    Error { status: StatusCode, message: String, code: Option<String> },
+    /// A body sent exactly as given
+    Raw(String),
+}
+
+/// What the mock sends back for a request
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    reply: Reply,
+    /// Characters of content per streamed chunk; 0 sends it in one chunk
+    chunk_size: usize,
+    /// Pause between streamed chunks
+    chunk_delay: Duration,
+    /// Pause before the response starts
+    delay: Duration,
+    /// Split the raw SSE bytes into frames of this size, cutting lines apart
+    frame_size: Option<usize>,
+    /// Drop the connection after this many content chunks
+    fail_after: Option<usize>,
+}
+
+impl MockResponse {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self::new(Reply::Text(content.into()))
+    }
+
+    pub fn echo() -> Self {
+        Self::new(Reply::Echo)
+    }
+
+    /// An HTTP error with an OpenAI-style error body
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::new(Reply::Error {
+            status: StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            message: message.into(),
+            code: None,
+        })
+    }
+
+    /// Set the `error.code` of an error response
+    pub fn code(mut self, error_code: impl Into<String>) -> Self {
+        if let Reply::Error { code, .. } = &mut self.reply {
+            *code = Some(error_code.into());
+        }
+        self
+    }
+
+    /// A body sent as-is, for responses a real provider should not send
+    pub fn raw(body: impl Into<String>) -> Self {
+        Self::new(Reply::Raw(body.into()))
+    }
+
+    fn new(reply: Reply) -> Self {
+        Self {
+            reply,
+            chunk_size: 8,
+            chunk_delay: Duration::ZERO,
+            delay: Duration::ZERO,
+            frame_size: None,
+            fail_after: None,
+        }
+    }
+
+    pub fn chunk_size(mut self, chars: usize) -> Self {
+        self.chunk_size = chars;
+        self
+    }
+
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn frame_size(mut self, bytes: usize) -> Self {
+        self.frame_size = Some(bytes.max(1));
+        self
+    }
+
+    pub fn fail_after(mut self, chunks: usize) -> Self {
+        self.fail_after = Some(chunks);
+        self
+    }
+
+    fn content(&self, request: &RecordedRequest) -> String {
+        match &self.reply {
+            Reply::Text(content) => content.clone(),
+            Reply::Echo => format!("[{}] {}", request.model, request.last_user_message()),
+            Reply::Error { .. } | Reply::Raw(_) => String::new(),
+        }
+    }
+}
+
+/// A request received by the mock
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub model: String,
+    pub stream: bool,
+    pub authorization: Option<String>,
+    pub body: Value,
+}
+
+impl RecordedRequest {
+    /// `(role, content)` of every message in the request
+    pub fn messages(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.body["messages"].as_array().into_iter().flatten().map(|m| {
+            (
+                m["role"].as_str().unwrap_or_default(),
+                m["content"].as_str().unwrap_or_default(),
+            )
+        })
+    }
+
+    pub fn system_prompt(&self) -> &str {
+        self.messages()
+            .find(|(role, _)| *role == "system")
+            .map_or("", |(_, content)| content)
+    }
+
+    pub fn last_user_message(&self) -> &str {
+        self.messages()
+            .filter(|(role, _)| *role == "user")
+            .last()
+            .map_or("", |(_, content)| content)
+    }
+}
+
+struct Rule {
+    matcher: Match,
+    response: MockResponse,
+    /// Removed after answering one request
+    once: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Mutex<Vec<Rule>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+/// Mock upstream server, shut down when dropped
+pub struct MockUpstream {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    task: JoinHandle<()>,
+}
+
+impl MockUpstream {
+    pub async fn start() -> Self {
+        let state = Arc::new(MockState::default());
+        let app = Router::new()
+            .route("/v1/chat/completions", post(chat_completions))
+            .with_state(Arc::clone(&state));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock upstream");
+        let addr = listener.local_addr().expect("mock upstream has no address");
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Self { addr, state, task }
+    }
+
+    /// Base URL to use as `api_url`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Answer every matching request with `response`
+    pub fn on(&self, matcher: Match, response: MockResponse) {
+        self.add_rule(matcher, response, false);
+    }
+
+    /// Answer the next matching request with `response`
+    ///
+    /// One-off rules are checked before the permanent ones.
+    pub fn once(&self, matcher: Match, response: MockResponse) {
+        self.add_rule(matcher, response, true);
+    }
+
+    /// Every request received so far, in arrival order
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Requests received for `model`
+    pub fn requests_for(&self, model: &str) -> Vec<RecordedRequest> {
+        self.requests().into_iter().filter(|r| r.model == model).collect()
+    }
+
+    fn add_rule(&self, matcher: Match, response: MockResponse, once: bool) {
+        let mut rules = self.state.rules.lock().unwrap();
+        let rule = Rule { matcher, response, once };
+        if once {
+            let first_permanent = rules.iter().position(|r| !r.once).unwrap_or(rules.len());
+            rules.insert(first_permanent, rule);
+        } else {
+            rules.push(rule);
+        }
+    }
+}
+
+impl Drop for MockUpstream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn chat_completions(State(state): State<Arc<MockState>>, headers: HeaderMap, Json(body): Json<Value>) -> Response {
+    let request = RecordedRequest {
+        model: body["model"].as_str().unwrap_or_default().to_string(),
+        stream: body["stream"].as_bool().unwrap_or(false),
+        authorization: headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body,
+    };
+    state.requests.lock().unwrap().push(request.clone());
+
+    let response = {
+        let mut rules = state.rules.lock().unwrap();
+        match rules.iter().position(|rule| rule.matcher.matches(&request)) {
+            Some(i) if rules[i].once => rules.remove(i).response,
+            Some(i) => rules[i].response.clone(),
+            None => MockResponse::echo(),
+        }
+    };
+
+    tokio::time::sleep(response.delay).await;
+
+    if let Reply::Error { status, message, code } = &response.reply {
+        let body = json!({ "error": { "message": message, "type": "mock_error", "code": code } });
+        return (*status, Json(body)).into_response();
+    }
+
+    if let Reply::Raw(body) = &response.reply {
+        let content_type = if request.stream { "text/event-stream" } else { "application/json" };
+        return Response::builder()
+            .header("Content-Type", content_type)
+            .body(Body::from(body.clone()))
+            .unwrap();
+    }
+
+    let content = response.content(&request);
+    if request.stream {
+        stream_response(&request.model, &content, &response)
+    } else {
+        Json(completion_json(&request.model, &content)).into_response()
+    }
+}
+
+fn completion_json(model: &str, content: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }
+    })
+}
+
+fn chunk_event(model: &str, delta: Value, finish_reason: Option<&str>) -> String {
+    let chunk = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+    });
+    format!("data: {}\n\n", chunk)
+}
+
+/// Stream `content` as server-sent events, paced and cut as configured
+fn stream_response(model: &str, content: &str, response: &MockResponse) -> Response {
+    let chars: Vec<char> = content.chars().collect();
+    let pieces: Vec<String> = if response.chunk_size == 0 || chars.is_empty() {
+        vec![content.to_string()]
+    } else {
+        chars.chunks(response.chunk_size).map(|c| c.iter().collect()).collect()
+    };
+
+    let mut events = vec![chunk_event(model, json!({ "role": "assistant" }), None)];
+    events.extend(pieces.iter().map(|piece| chunk_event(model, json!({ "content": piece }), None)));
+    // Only content chunks count towards `fail_after`; the role chunk comes first
+    let fail_at = response.fail_after.map(|n| n + 1);
+    events.push(chunk_event(model, json!({}), Some("stop")));
+    events.push("data: [DONE]\n\n".to_string());
+
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(16);
+    let response = response.clone();
+    tokio::spawn(async move {
+        for (i, event) in events.iter().enumerate() {
+            if fail_at == Some(i) {
+                let _ = tx.send(Err(std::io::Error::other("mock upstream dropped the stream"))).await;
+                return;
+            }
+            if i > 0 {
+                tokio::time::sleep(response.chunk_delay).await;
+            }
+            for frame in frames(event, response.frame_size) {
+                if tx.send(Ok(Bytes::from(frame))).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .body(Body::from_stream(ReceiverStream::new(rx)))
+        .unwrap()
+}
+
+/// Split an event into frames of at most `size` bytes, on character boundaries
+fn frames(event: &str, size: Option<usize>) -> Vec<String> {
+    let Some(size) = size else {
+        return vec![event.to_string()];
+    };
+    let mut frames = Vec::new();
+    let mut current = String::new();
+    for c in event.chars() {
+        if !current.is_empty() && current.len() + c.len_utf8() > size {
+            frames.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        frames.push(current);
+    }
+    frames
+}
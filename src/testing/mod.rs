@@ -0,0 +1,91 @@
+//! Test utilities: a mock upstream and helpers to run the API server against it
+//!
+//! Used by the integration tests under `tests/`, which run offline.
+
+pub mod mock_upstream;
+
+pub use mock_upstream::{Match, MockResponse, MockUpstream, RecordedRequest};
+
+use std::sync::Arc;
+
+use reqwest::Client;
+use tokio::task::JoinHandle;
+
+use crate::api::server::{AppState, router};
+use crate::config::{Config, Pipeline};
+use crate::core::adaptive::{ReasoningBudget, ReasoningMode};
+use crate::core::classifier::ClassifierConfig;
+
+/// Reasoning model of the test configuration
+pub const REASONING_MODEL: &str = "mock-reasoner";
+/// Crafting model of the test configuration
+pub const CRAFT_MODEL: &str = "mock-crafter";
+
+/// A configuration pointing at `api_url` that ignores the environment
+///
+/// Reasoning always runs in full, and no files are read or written: prompts
+/// are the built-in ones and history, sessions and caching are off.
+pub fn test_config(api_url: &str) -> Config {
+    let mut config = Config {
+        reasoning_model: REASONING_MODEL.to_string(),
+        craft_model: CRAFT_MODEL.to_string(),
+        temperature: 0.7,
+        api_url: api_url.to_string(),
+        api_key: "test-key".to_string(),
+        host: "127.0.0.1".to_string(),
+        port: 0,
+        unix_socket: None,
+        tls_cert: None,
+        tls_key: None,
+        config_file: None,
+        pipelines: Vec::new(),
+        pipeline: None,
+        stages: Vec::new(),
+        critique: None,
+        reasoning_ensemble: Vec::new(),
+        aggregator_model: None,
+        classifier: ClassifierConfig::default(),
+        // A directory that never exists, so the built-in prompts are used
+        prompts_dir: "/nonexistent/dualmind-prompts".to_string(),
+        pipeline_prompts_dir: None,
+        cache: None,
+        reasoning_mode: ReasoningMode::Full,
+        reasoning_budget: ReasoningBudget::default(),
+        plain: true,
+        history_file: None,
+        sessions_dir: None,
+        record_dir: None,
+        replay_dir: None,
+    };
+    config.pipelines = vec![Pipeline::default_for(&config)];
+    config
+}
+
+/// The API server running on an ephemeral port, shut down when dropped
+pub struct TestServer {
+    pub url: String,
+    pub state: Arc<AppState>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Start the API server with `config`
+pub async fn spawn_api(config: Config) -> TestServer {
+    let state = Arc::new(AppState::new(Client::new(), config).expect("failed to create server state"));
+    let app = router(Arc::clone(&state));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind API server");
+    let url = format!("http://{}", listener.local_addr().expect("API server has no address"));
+    let task = tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    TestServer { url, state, task }
+}
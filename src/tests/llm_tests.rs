@@ -1,4 +0,0 @@
-#[test]
-fn test_reasoner_call() {
-    // Test implementation
-} 
\ No newline at end of file
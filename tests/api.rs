@@ -0,0 +1,366 @@
+//! End-to-end tests of the API server against the mock upstream
+
+use dualmind::testing::{CRAFT_MODEL, MockResponse, MockUpstream, Match, REASONING_MODEL, TestServer, spawn_api, test_config};
+use dualmind::config::cache::CacheConfig;
+use dualmind::config::Pipeline;
+use dualmind::config::pipelines::CritiqueConfig;
+use serde_json::{Value, json};
+
+async fn setup() -> (MockUpstream, TestServer) {
+    let upstream = MockUpstream::start().await;
+    let server = spawn_api(test_config(&upstream.url())).await;
+    (upstream, server)
+}
+
+async fn post_chat(server: &TestServer, body: Value, session: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.url))
+        .json(&body);
+    if let Some(session) = session {
+        request = request.header("X-Session-ID", session);
+    }
+    request.send().await.expect("request to the API server failed")
+}
+
+fn chat_body(question: &str) -> Value {
+    json!({
+        "model": "dualmind",
+        "messages": [{ "role": "user", "content": question }]
+    })
+}
+
+/// Contents of the `data:` events of an SSE body
+fn sse_events(body: &str) -> Vec<&str> {
+    body.lines().filter_map(|line| line.strip_prefix("data: ")).collect()
+}
+
+/// The streamed answer, one entry per content chunk
+fn sse_deltas(body: &str) -> Vec<String> {
+    sse_events(body)
+        .into_iter()
+        .filter_map(|event| serde_json::from_str::<Value>(event).ok())
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+        .collect()
+}
+
+#[tokio::test]
+async fn completion_returns_crafted_answer() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("REASONING-A"));
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("ANSWER-A"));
+
+    let response = post_chat(&server, chat_body("What is 2 + 2?"), None).await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("x-session-id"));
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["object"], "chat.completion");
+    assert_eq!(body["model"], "dualmind");
+    assert_eq!(body["choices"][0]["message"]["content"], "ANSWER-A");
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].model, REASONING_MODEL);
+    assert_eq!(requests[1].model, CRAFT_MODEL);
+    assert!(requests.iter().all(|r| r.stream));
+    assert_eq!(requests[0].authorization.as_deref(), Some("Bearer test-key"));
+    assert_eq!(requests[0].last_user_message(), "What is 2 + 2?");
+}
+
+#[tokio::test]
+async fn reasoning_is_passed_to_the_crafter() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("Add the two numbers: 4."));
+
+    let response = post_chat(&server, chat_body("What is 2 + 2?"), None).await;
+    assert_eq!(response.status(), 200);
+
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    assert_eq!(crafter.len(), 1);
+    let prompt: String = crafter[0].messages().map(|(_, content)| content).collect();
+    assert!(prompt.contains("Add the two numbers: 4."));
+    assert!(!upstream.requests_for(REASONING_MODEL)[0].system_prompt().contains("Add the two numbers"));
+}
+
+#[tokio::test]
+async fn streaming_sends_answer_chunks() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("private reasoning"));
+    upstream.on(
+        Match::Model(CRAFT_MODEL.into()),
+        MockResponse::text("The quick brown fox jumps over the lazy dog")
+            .chunk_size(5)
+            .frame_size(7),
+    );
+
+    let mut body = chat_body("Tell me about foxes");
+    body["stream"] = json!(true);
+    let response = post_chat(&server, body, None).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let text = response.text().await.unwrap();
+
+    let deltas = sse_deltas(&text);
+    assert!(deltas.len() > 1, "answer arrived in one chunk: {:?}", deltas);
+    assert_eq!(deltas.concat(), "The quick brown fox jumps over the lazy dog");
+    assert!(!text.contains("private reasoning"));
+    assert_eq!(sse_events(&text).last(), Some(&"[DONE]"));
+}
+
+#[tokio::test]
+async fn streaming_sends_critique_metadata_only_when_a_round_ran() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("APPROVED"));
+    let critique_chunks = |rounds| {
+        let mut config = test_config(&upstream.url());
+        config.critique = Some(CritiqueConfig {
+            model: None,
+            max_rounds: rounds,
+        });
+        config.pipelines = vec![Pipeline::default_for(&config)];
+        async move {
+            let server = spawn_api(config).await;
+            let mut body = chat_body("Hello");
+            body["stream"] = json!(true);
+            let text = post_chat(&server, body, None).await.text().await.unwrap();
+            sse_events(&text)
+                .into_iter()
+                .filter_map(|event| serde_json::from_str::<Value>(event).ok())
+                .filter(|chunk| chunk.get("critique").is_some())
+                .count()
+        }
+    };
+
+    assert_eq!(critique_chunks(0).await, 0);
+    assert_eq!(critique_chunks(1).await, 1);
+}
+
+#[tokio::test]
+async fn session_keeps_history_between_requests() {
+    let (upstream, server) = setup().await;
+
+    let first = post_chat(&server, chat_body("My name is Ada"), Some("session-1")).await;
+    assert_eq!(first.headers()["x-session-id"], "session-1");
+    let first: Value = first.json().await.unwrap();
+    let first_answer = first["choices"][0]["message"]["content"].as_str().unwrap().to_string();
+
+    post_chat(&server, chat_body("What is my name?"), Some("session-1")).await;
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    let history: Vec<(&str, &str)> = crafter[1].messages().filter(|(role, _)| *role != "system").collect();
+    assert_eq!(
+        history,
+        vec![("user", "My name is Ada"), ("assistant", first_answer.as_str()), ("user", "What is my name?")]
+    );
+
+    // Other sessions do not see it
+    post_chat(&server, chat_body("What is my name?"), Some("session-2")).await;
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    assert!(crafter[2].messages().all(|(_, content)| !content.contains("Ada")));
+}
+
+#[tokio::test]
+async fn failed_turns_are_not_added_to_the_session() {
+    let (upstream, server) = setup().await;
+    upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::error(500, "crafter exploded"));
+
+    let failed = post_chat(&server, chat_body("My name is Ada"), Some("session-1")).await;
+    assert_eq!(failed.status(), 502);
+
+    // The retry is sent once, with no unanswered turn before it
+    post_chat(&server, chat_body("My name is Ada"), Some("session-1")).await;
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    let history: Vec<(&str, &str)> = crafter[1].messages().filter(|(role, _)| *role != "system").collect();
+    assert_eq!(history, vec![("user", "My name is Ada")]);
+}
+
+#[tokio::test]
+async fn cleared_session_starts_over() {
+    let (upstream, server) = setup().await;
+    post_chat(&server, chat_body("My name is Ada"), Some("session-1")).await;
+
+    let client = reqwest::Client::new();
+    let cleared = client
+        .post(format!("{}/v1/sessions/session-1/clear", server.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cleared.status(), 200);
+    let missing = client
+        .post(format!("{}/v1/sessions/session-1/clear", server.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    post_chat(&server, chat_body("What is my name?"), Some("session-1")).await;
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    assert!(crafter[1].messages().all(|(_, content)| !content.contains("Ada")));
+}
+
+#[tokio::test]
+async fn skipped_reasoning_reuses_the_previous_turn() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("REASONING-FROM-TURN-1"));
+
+    post_chat(&server, chat_body("Explain ownership"), Some("session-1")).await;
+    let mut body = chat_body("Shorter please");
+    body["reasoning_mode"] = json!("skip");
+    let response = post_chat(&server, body, Some("session-1")).await;
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(upstream.requests_for(REASONING_MODEL).len(), 1);
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    assert_eq!(crafter.len(), 2);
+    let prompt: String = crafter[1].messages().map(|(_, content)| content).collect();
+    assert!(prompt.contains("REASONING-FROM-TURN-1"));
+}
+
+#[tokio::test]
+async fn upstream_error_is_reported() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::error(503, "model overloaded"));
+
+    let response = post_chat(&server, chat_body("Hello"), None).await;
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "api_error");
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("reasoning stage"), "{}", message);
+    assert!(message.contains("model overloaded"), "{}", message);
+    assert!(upstream.requests_for(CRAFT_MODEL).is_empty());
+}
+
+#[tokio::test]
+async fn streaming_upstream_error_is_sent_as_an_event() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::error(500, "crafter exploded"));
+
+    let mut body = chat_body("Hello");
+    body["stream"] = json!(true);
+    let text = post_chat(&server, body, None).await.text().await.unwrap();
+
+    let errors: Vec<Value> = sse_events(&text)
+        .into_iter()
+        .filter_map(|event| serde_json::from_str::<Value>(event).ok())
+        .filter(|event| event.get("error").is_some())
+        .collect();
+    assert_eq!(errors.len(), 1, "{}", text);
+    assert!(errors[0]["error"]["message"].as_str().unwrap().contains("crafter exploded"));
+}
+
+#[tokio::test]
+async fn upstream_errors_keep_their_status_and_code() {
+    let cases = [
+        (MockResponse::error(401, "Incorrect API key"), 401, "authentication_error", "invalid_api_key"),
+        (MockResponse::error(429, "Slow down"), 429, "rate_limit_error", "rate_limit_exceeded"),
+        (
+            MockResponse::error(429, "You exceeded your quota").code("insufficient_quota"),
+            429,
+            "rate_limit_error",
+            "insufficient_quota",
+        ),
+        (MockResponse::error(404, "No such model"), 404, "invalid_request_error", "model_not_found"),
+        (
+            MockResponse::error(400, "This model's maximum context length is 8192 tokens")
+                .code("context_length_exceeded"),
+            400,
+            "invalid_request_error",
+            "context_length_exceeded",
+        ),
+        (MockResponse::error(504, "Gateway timeout"), 504, "timeout_error", "timeout"),
+    ];
+
+    for (upstream_response, status, error_type, code) in cases {
+        let (upstream, server) = setup().await;
+        upstream.on(Match::Model(CRAFT_MODEL.into()), upstream_response);
+
+        let response = post_chat(&server, chat_body("Hello"), None).await;
+        assert_eq!(response.status(), status);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], error_type, "{}", body);
+        assert_eq!(body["error"]["code"], code, "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn streaming_error_event_is_classified() {
+    let (upstream, server) = setup().await;
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::error(429, "Slow down"));
+
+    let mut body = chat_body("Hello");
+    body["stream"] = json!(true);
+    let text = post_chat(&server, body, None).await.text().await.unwrap();
+
+    let error = sse_events(&text)
+        .into_iter()
+        .filter_map(|event| serde_json::from_str::<Value>(event).ok())
+        .find(|event| event.get("error").is_some())
+        .expect("no error event");
+    assert_eq!(error["error"]["type"], "rate_limit_error");
+    assert_eq!(error["error"]["code"], "rate_limit_exceeded");
+}
+
+#[tokio::test]
+async fn dropped_upstream_stream_fails_the_request() {
+    let (upstream, server) = setup().await;
+    upstream.on(
+        Match::Model(REASONING_MODEL.into()),
+        MockResponse::text("this stream never finishes").chunk_size(4).fail_after(2),
+    );
+
+    let response = post_chat(&server, chat_body("Hello"), None).await;
+    assert_eq!(response.status(), 502);
+    assert!(upstream.requests_for(CRAFT_MODEL).is_empty());
+}
+
+#[tokio::test]
+async fn unknown_model_is_not_found() {
+    let (upstream, server) = setup().await;
+
+    let mut body = chat_body("Hello");
+    body["model"] = json!("no-such-pipeline");
+    let response = post_chat(&server, body, None).await;
+    assert_eq!(response.status(), 404);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "model_not_found");
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn models_lists_the_pipelines() {
+    let (_upstream, server) = setup().await;
+
+    let body: Value = reqwest::get(format!("{}/v1/models", server.url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = body["data"].as_array().unwrap().iter().filter_map(|m| m["id"].as_str()).collect();
+    assert_eq!(ids, vec!["dualmind"]);
+}
+
+#[tokio::test]
+async fn request_temperature_decides_caching() {
+    let upstream = MockUpstream::start().await;
+    let mut config = test_config(&upstream.url());
+    config.cache = Some(CacheConfig::default());
+    let server = spawn_api(config).await;
+    let cache_status = |response: &reqwest::Response| response.headers()["x-dualmind-cache"].to_str().unwrap().to_string();
+
+    // The configured temperature of 0.7 is not cached
+    let response = post_chat(&server, chat_body("Hello"), None).await;
+    assert_eq!(cache_status(&response), "BYPASS");
+    let temperature = upstream.requests_for(CRAFT_MODEL)[0].body["temperature"].as_f64().unwrap();
+    assert!((temperature - 0.7).abs() < 1e-6);
+
+    // A request for temperature 0 is sampled at 0 and cached
+    let mut body = chat_body("Hello");
+    body["temperature"] = json!(0.0);
+    let response = post_chat(&server, body.clone(), None).await;
+    assert_eq!(cache_status(&response), "MISS");
+    assert_eq!(upstream.requests_for(CRAFT_MODEL)[1].body["temperature"], json!(0.0));
+
+    let response = post_chat(&server, body, None).await;
+    assert_eq!(cache_status(&response), "HIT");
+    assert_eq!(upstream.requests_for(CRAFT_MODEL).len(), 2);
+}
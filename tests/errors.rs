@@ -0,0 +1,64 @@
+//! Classifying upstream failures
+
+use dualmind::error::{Error, ErrorKind};
+use serde_json::json;
+
+#[test]
+fn upstream_statuses_are_classified() {
+    let cases = [
+        (401, ErrorKind::UpstreamAuth),
+        (403, ErrorKind::UpstreamAuth),
+        (429, ErrorKind::RateLimited),
+        (404, ErrorKind::ModelNotFound),
+        (413, ErrorKind::ContextLengthExceeded),
+        (408, ErrorKind::Timeout),
+        (400, ErrorKind::InvalidRequest),
+        (500, ErrorKind::Upstream),
+        (503, ErrorKind::Upstream),
+    ];
+    for (status, kind) in cases {
+        let error = Error::from_upstream(status, "not json");
+        assert_eq!(error.kind(), kind, "status {}", status);
+        assert_eq!(error.upstream_status(), Some(status));
+    }
+}
+
+#[test]
+fn error_bodies_refine_the_status() {
+    let body = json!({ "error": { "message": "too long", "type": "invalid_request_error", "code": "context_length_exceeded" } });
+    let error = Error::from_upstream(400, &body.to_string());
+    assert_eq!(error.kind(), ErrorKind::ContextLengthExceeded);
+    assert_eq!(error.upstream_code(), Some(&json!("context_length_exceeded")));
+    assert!(error.message().contains("too long"), "{}", error);
+
+    // Anthropic reports an oversized prompt in the message only
+    let body = json!({ "error": { "type": "invalid_request_error", "message": "prompt is too long: 210000 tokens > 200000 maximum" } });
+    assert_eq!(Error::from_upstream(400, &body.to_string()).kind(), ErrorKind::ContextLengthExceeded);
+
+    // A 400 naming an unknown model
+    let body = json!({ "error": { "message": "no such model", "code": "model_not_found" } });
+    assert_eq!(Error::from_upstream(400, &body.to_string()).kind(), ErrorKind::ModelNotFound);
+}
+
+#[test]
+fn response_bodies_use_openai_types_and_codes() {
+    // Numeric codes, as OpenRouter sends them, are replaced by the OpenAI one
+    let body = json!({ "error": { "message": "Rate limit exceeded", "code": 429 } });
+    let response = Error::from_upstream(429, &body.to_string()).to_response();
+    assert_eq!(response.error.kind.as_deref(), Some("rate_limit_error"));
+    assert_eq!(response.error.code, Some(json!("rate_limit_exceeded")));
+
+    let response = Error::internal("boom").context("Error in crafter stage").to_response();
+    assert_eq!(response.error.message, "Error in crafter stage: boom");
+    assert_eq!(response.error.kind.as_deref(), Some("api_error"));
+    assert_eq!(response.error.code, Some(json!(500)));
+}
+
+#[test]
+fn boxed_errors_keep_their_kind() {
+    let boxed: Box<dyn std::error::Error + Send + Sync> = Error::from_upstream(401, "denied").into();
+    assert_eq!(Error::from(boxed).kind(), ErrorKind::UpstreamAuth);
+
+    let boxed: Box<dyn std::error::Error + Send + Sync> = "something else".into();
+    assert_eq!(Error::from(boxed).kind(), ErrorKind::Internal);
+}
@@ -0,0 +1,82 @@
+//! The typed OpenAI wire format
+
+use dualmind::models::Role;
+use dualmind::models::openai::{ChatChunk, ChatMessage, ChatRequest, ChatResponse};
+use serde_json::{Value, json};
+
+#[test]
+fn unknown_fields_pass_through() {
+    let body = json!({
+        "model": "m",
+        "messages": [{ "role": "user", "content": "hi", "name": "ada" }],
+        "temperature": 0.5,
+        "top_p": 0.9,
+        "response_format": { "type": "json_object" }
+    });
+    let request: ChatRequest = serde_json::from_value(body.clone()).unwrap();
+    assert_eq!(request.extra["top_p"], 0.9);
+    assert_eq!(request.messages[0].extra["name"], "ada");
+    assert_eq!(serde_json::to_value(&request).unwrap(), body);
+}
+
+#[test]
+fn requests_omit_unset_fields() {
+    let request = ChatRequest::new("m", vec![ChatMessage::new(Role::System, "be brief")]);
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        json!({ "model": "m", "messages": [{ "role": "system", "content": "be brief" }] })
+    );
+}
+
+#[test]
+fn response_content_is_extracted() {
+    let response = ChatResponse::parse(
+        r#"{"id": "c", "choices": [{"index": 0, "message": {"role": "assistant", "content": "42"}, "finish_reason": "stop"}], "system_fingerprint": "fp"}"#,
+    )
+    .unwrap();
+    assert_eq!(response.extra["system_fingerprint"], "fp");
+    assert_eq!(response.into_content().unwrap(), "42");
+}
+
+#[test]
+fn unexpected_responses_are_errors() {
+    let error = ChatResponse::parse(r#"{"result": "42"}"#).unwrap_err().to_string();
+    assert!(error.starts_with("Unexpected upstream response"), "{}", error);
+
+    let error = ChatResponse::parse(r#"{"error": {"message": "bad key", "type": "auth"}}"#).unwrap_err().to_string();
+    assert_eq!(error, "Upstream error: bad key");
+
+    let empty = ChatResponse::parse(r#"{"choices": []}"#).unwrap();
+    assert_eq!(empty.into_content().unwrap_err().to_string(), "Upstream response has no choices");
+
+    let refused = ChatResponse::parse(
+        r#"{"choices": [{"message": {"role": "assistant", "content": null, "refusal": "No."}, "finish_reason": "stop"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(refused.into_content().unwrap_err().to_string(), "The model refused: No.");
+
+    let filtered = ChatResponse::parse(
+        r#"{"choices": [{"message": {"role": "assistant"}, "finish_reason": "content_filter"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        filtered.into_content().unwrap_err().to_string(),
+        "Upstream response has no content (finish_reason: content_filter)"
+    );
+}
+
+#[test]
+fn chunks_match_the_openai_format() {
+    let chunk = ChatChunk::new("id", 1, "dualmind", Default::default());
+    let value: Value = serde_json::to_value(&chunk).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "id": "id",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "dualmind",
+            "choices": [{ "index": 0, "delta": {}, "finish_reason": null }]
+        })
+    );
+}
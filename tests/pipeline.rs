@@ -0,0 +1,242 @@
+//! End-to-end tests of the pipeline the terminal chat runs, against the mock upstream
+
+use std::time::Duration;
+
+use dualmind::config::Config;
+use dualmind::core::adaptive::ReasoningMode;
+use dualmind::core::pipeline::{PipelineEvent, PipelineOutput, StageOutput, run_pipeline};
+use dualmind::models::{Message, Role};
+use dualmind::testing::{CRAFT_MODEL, MockResponse, MockUpstream, Match, REASONING_MODEL, test_config};
+use reqwest::Client;
+use tokio::sync::mpsc;
+
+fn user(content: &str) -> Vec<Message> {
+    vec![Message {
+        role: Role::User,
+        content: content.to_string(),
+    }]
+}
+
+/// Run the pipeline, collecting the events it emits
+async fn run(config: &Config, messages: &[Message], previous: &[StageOutput]) -> (PipelineOutput, Vec<PipelineEvent>) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let output = run_pipeline(&Client::new(), config, messages, previous, Some(tx))
+        .await
+        .expect("pipeline failed");
+    let mut events = Vec::new();
+    while let Ok(event) = rx.try_recv() {
+        events.push(event);
+    }
+    (output, events)
+}
+
+/// Content streamed for the stage at `index`
+fn streamed(events: &[PipelineEvent], stage: usize) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            PipelineEvent::Content { index, content } if *index == stage => Some(content.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn stages_stream_in_order() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("Think it through").chunk_size(3));
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("Final answer").chunk_size(3));
+    let config = test_config(&upstream.url());
+
+    let (output, events) = run(&config, &user("Why is the sky blue?"), &[]).await;
+    assert_eq!(output.reasoning_mode, ReasoningMode::Full);
+    assert_eq!(output.answer(), "Final answer");
+    assert_eq!(output.reasoning_stages()[0].content, "Think it through");
+    assert_eq!(streamed(&events, 0), "Think it through");
+    assert_eq!(streamed(&events, 1), "Final answer");
+
+    let lifecycle: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            PipelineEvent::StageStarted { index, model, .. } => Some(format!("start {} {}", index, model)),
+            PipelineEvent::StageFinished { index } => Some(format!("finish {}", index)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        lifecycle,
+        vec![
+            format!("start 0 {}", REASONING_MODEL),
+            "finish 0".to_string(),
+            format!("start 1 {}", CRAFT_MODEL),
+            "finish 1".to_string(),
+        ]
+    );
+    assert!(matches!(events[0], PipelineEvent::TaskClassified { .. }));
+}
+
+#[tokio::test]
+async fn reasoning_reaches_the_crafter_prompt() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("Photons scatter: Rayleigh."));
+    let config = test_config(&upstream.url());
+
+    run(&config, &user("Why is the sky blue?"), &[]).await;
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    assert_eq!(crafter.len(), 1);
+    let prompt: String = crafter[0].messages().map(|(_, content)| content).collect();
+    assert!(prompt.contains("Photons scatter: Rayleigh."));
+}
+
+#[tokio::test]
+async fn skip_mode_reuses_previous_reasoning() {
+    let upstream = MockUpstream::start().await;
+    let mut config = test_config(&upstream.url());
+    config.reasoning_mode = ReasoningMode::Skip;
+    let previous = vec![StageOutput {
+        name: "reasoning".to_string(),
+        model: REASONING_MODEL.to_string(),
+        content: "EARLIER-REASONING".to_string(),
+    }];
+
+    let (output, _) = run(&config, &user("And in more detail?"), &previous).await;
+    assert_eq!(output.reasoning_mode, ReasoningMode::Skip);
+    assert!(upstream.requests_for(REASONING_MODEL).is_empty());
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    let prompt: String = crafter[0].messages().map(|(_, content)| content).collect();
+    assert!(prompt.contains("EARLIER-REASONING"));
+}
+
+#[tokio::test]
+async fn slow_reasoning_is_cut_off_at_the_time_budget() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(REASONING_MODEL.into()),
+        MockResponse::text("step ".repeat(100))
+            .chunk_size(5)
+            .chunk_delay(Duration::from_millis(50)),
+    );
+    let mut config = test_config(&upstream.url());
+    config.reasoning_budget.max_seconds = Some(1);
+
+    let (output, events) = run(&config, &user("Think hard"), &[]).await;
+    assert!(events.iter().any(|e| matches!(e, PipelineEvent::StageCutOff { index: 0 })));
+    let reasoning = &output.reasoning_stages()[0].content;
+    assert!(!reasoning.is_empty() && reasoning.len() < 499, "{:?}", reasoning);
+    assert_eq!(output.answer(), format!("[{}] Think hard", CRAFT_MODEL));
+}
+
+#[tokio::test]
+async fn ensemble_survives_a_failing_member() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model("mock-broken".into()), MockResponse::error(500, "member down"));
+    upstream.on(Match::Model("mock-second".into()), MockResponse::text("Second opinion"));
+    let mut config = test_config(&upstream.url());
+    config.reasoning_ensemble = vec!["mock-broken".to_string(), "mock-second".to_string()];
+
+    let (output, events) = run(&config, &user("Compare the options"), &[]).await;
+    let failures: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            PipelineEvent::EnsembleMemberFinished { model, error: Some(_), .. } => Some(model.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(failures, vec!["mock-broken"]);
+    assert!(output.reasoning_stages()[0].content.contains("Second opinion"));
+}
+
+#[tokio::test]
+async fn transient_failure_fails_the_turn_and_the_next_succeeds() {
+    let upstream = MockUpstream::start().await;
+    upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::error(429, "rate limited"));
+    let config = test_config(&upstream.url());
+
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Hello"), &[], None).await else {
+        panic!("the first turn should fail");
+    };
+    assert!(error.to_string().contains("rate limited"), "{}", error);
+
+    let (output, _) = run(&config, &user("Hello"), &[]).await;
+    assert_eq!(output.answer(), format!("[{}] Hello", CRAFT_MODEL));
+}
+
+#[tokio::test]
+async fn delayed_upstream_is_cut_off_before_it_answers() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(REASONING_MODEL.into()),
+        MockResponse::text("too late").delay(Duration::from_secs(3)),
+    );
+    let mut config = test_config(&upstream.url());
+    config.reasoning_budget.max_seconds = Some(1);
+
+    let (output, events) = run(&config, &user("Quick question"), &[]).await;
+    assert!(events.iter().any(|e| matches!(e, PipelineEvent::StageCutOff { index: 0 })));
+    assert_eq!(output.reasoning_stages()[0].content, "");
+    assert_eq!(upstream.requests_for(CRAFT_MODEL).len(), 1);
+}
+
+#[tokio::test]
+async fn malformed_stream_chunk_is_an_error() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(CRAFT_MODEL.into()),
+        MockResponse::raw("data: {\"choices\": \"not a list\"}\n\ndata: [DONE]\n\n"),
+    );
+    let config = test_config(&upstream.url());
+
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Hello"), &[], None).await else {
+        panic!("a malformed chunk should fail the turn");
+    };
+    assert!(error.to_string().contains("Unexpected upstream stream chunk"), "{}", error);
+}
+
+#[tokio::test]
+async fn refusal_is_an_error_rather_than_an_empty_answer() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(CRAFT_MODEL.into()),
+        MockResponse::raw(
+            "data: {\"choices\": [{\"index\": 0, \"delta\": {\"refusal\": \"I can't help with that.\"}}]}\n\ndata: [DONE]\n\n",
+        ),
+    );
+    let config = test_config(&upstream.url());
+
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Hello"), &[], None).await else {
+        panic!("a refusal should fail the turn");
+    };
+    assert!(error.to_string().contains("The model refused: I can't help with that."), "{}", error);
+}
+
+#[tokio::test]
+async fn error_event_in_the_stream_is_reported() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(REASONING_MODEL.into()),
+        MockResponse::raw(
+            "data: {\"choices\": [{\"delta\": {\"content\": \"Partial\"}}]}\n\ndata: {\"error\": {\"message\": \"context length exceeded\", \"code\": 400}}\n\n",
+        ),
+    );
+    let config = test_config(&upstream.url());
+
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Hello"), &[], None).await else {
+        panic!("an error event should fail the turn");
+    };
+    assert!(error.to_string().contains("Upstream error: context length exceeded"), "{}", error);
+}
+
+#[tokio::test]
+async fn whole_completion_is_accepted_when_stream_is_ignored() {
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(CRAFT_MODEL.into()),
+        MockResponse::raw(
+            "{\n  \"id\": \"x\",\n  \"choices\": [{\"index\": 0, \"message\": {\"role\": \"assistant\", \"content\": \"Not streamed\"}, \"finish_reason\": \"stop\"}]\n}\n",
+        ),
+    );
+    let config = test_config(&upstream.url());
+
+    let (output, _) = run(&config, &user("Hello"), &[]).await;
+    assert_eq!(output.answer(), "Not streamed");
+}
@@ -0,0 +1,111 @@
+//! Recording upstream traffic and replaying it without the network
+
+use std::time::Duration;
+
+use dualmind::core::pipeline::{PipelineEvent, run_pipeline};
+use dualmind::core::recording::Exchange;
+use dualmind::models::{Message, Role};
+use dualmind::testing::{CRAFT_MODEL, MockResponse, MockUpstream, Match, REASONING_MODEL, test_config};
+use reqwest::Client;
+use tokio::sync::mpsc;
+
+fn user(content: &str) -> Vec<Message> {
+    vec![Message {
+        role: Role::User,
+        content: content.to_string(),
+    }]
+}
+
+fn recordings(dir: &std::path::Path) -> Vec<Exchange> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| serde_json::from_str(&std::fs::read_to_string(entry.path()).unwrap()).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn replay_reproduces_a_recorded_turn() {
+    let dir = tempfile::tempdir().unwrap();
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("Recorded reasoning").chunk_size(4));
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("Recorded answer").chunk_size(4));
+
+    let mut config = test_config(&upstream.url());
+    config.record_dir = Some(dir.path().to_string_lossy().into_owned());
+    let recorded = run_pipeline(&Client::new(), &config, &user("Hello"), &[], None).await.unwrap();
+    assert_eq!(recorded.answer(), "Recorded answer");
+
+    let exchanges = recordings(dir.path());
+    assert_eq!(exchanges.len(), 2);
+    assert!(exchanges.iter().all(|e| e.status == Some(200) && e.chunks.len() > 1));
+
+    // The upstream is gone; everything comes from the recordings
+    let url = upstream.url();
+    drop(upstream);
+    let mut config = test_config(&url);
+    config.replay_dir = Some(dir.path().to_string_lossy().into_owned());
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let replayed = run_pipeline(&Client::new(), &config, &user("Hello"), &[], Some(tx)).await.unwrap();
+    assert_eq!(replayed.answer(), "Recorded answer");
+    assert_eq!(replayed.reasoning_stages()[0].content, "Recorded reasoning");
+
+    let mut answer_chunks = 0;
+    while let Ok(event) = rx.try_recv() {
+        if matches!(event, PipelineEvent::Content { index: 1, .. }) {
+            answer_chunks += 1;
+        }
+    }
+    assert!(answer_chunks > 1, "the replay should be streamed as recorded");
+}
+
+#[tokio::test]
+async fn replay_fails_for_an_unrecorded_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config("http://127.0.0.1:9");
+    config.replay_dir = Some(dir.path().to_string_lossy().into_owned());
+
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Never recorded"), &[], None).await else {
+        panic!("replaying an unrecorded request should fail");
+    };
+    assert!(error.to_string().contains("No recording"), "{}", error);
+}
+
+#[tokio::test]
+async fn replay_keeps_recorded_errors_and_timing() {
+    let dir = tempfile::tempdir().unwrap();
+    let upstream = MockUpstream::start().await;
+    upstream.on(
+        Match::Model(REASONING_MODEL.into()),
+        MockResponse::text("step ".repeat(60))
+            .chunk_size(5)
+            .chunk_delay(Duration::from_millis(100)),
+    );
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::error(503, "overloaded"));
+
+    let mut config = test_config(&upstream.url());
+    config.reasoning_budget.max_seconds = Some(1);
+    config.record_dir = Some(dir.path().to_string_lossy().into_owned());
+    let recorded = run_pipeline(&Client::new(), &config, &user("Think"), &[], None).await;
+    assert!(recorded.is_err());
+
+    // The reasoning stream was cut off at its budget and saved as far as it got
+    let exchanges = recordings(dir.path());
+    let reasoning = exchanges.iter().find(|e| e.request["model"] == REASONING_MODEL).unwrap();
+    assert!(reasoning.chunks.len() < 60);
+    assert!(reasoning.abandoned_ms.is_some());
+
+    drop(upstream);
+    config.record_dir = None;
+    config.replay_dir = Some(dir.path().to_string_lossy().into_owned());
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let Err(error) = run_pipeline(&Client::new(), &config, &user("Think"), &[], Some(tx)).await else {
+        panic!("the recorded error should be replayed");
+    };
+    assert!(error.to_string().contains("overloaded"), "{}", error);
+    let mut cut_off = false;
+    while let Ok(event) = rx.try_recv() {
+        cut_off |= matches!(event, PipelineEvent::StageCutOff { index: 0 });
+    }
+    assert!(cut_off, "the replayed reasoning should hit the same budget");
+}
@@ -0,0 +1,199 @@
+//! End-to-end tests of the Responses API against the mock upstream
+
+use dualmind::testing::{CRAFT_MODEL, MockResponse, MockUpstream, Match, REASONING_MODEL, TestServer, spawn_api, test_config};
+use serde_json::{Value, json};
+
+async fn setup() -> (MockUpstream, TestServer) {
+    let upstream = MockUpstream::start().await;
+    upstream.on(Match::Model(REASONING_MODEL.into()), MockResponse::text("Think it through").chunk_size(4));
+    upstream.on(Match::Model(CRAFT_MODEL.into()), MockResponse::text("The answer is 42").chunk_size(4));
+    let server = spawn_api(test_config(&upstream.url())).await;
+    (upstream, server)
+}
+
+async fn post_response(server: &TestServer, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/v1/responses", server.url))
+        .json(&body)
+        .send()
+        .await
+        .expect("request to the API server failed")
+}
+
+/// `(event, data)` of every server-sent event
+fn sse_events(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter_map(|event| {
+            let mut lines = event.lines();
+            let name = lines.next()?.strip_prefix("event: ")?;
+            let data = lines.next()?.strip_prefix("data: ")?;
+            Some((name.to_string(), serde_json::from_str(data).unwrap()))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn response_has_reasoning_summary_and_answer() {
+    let (upstream, server) = setup().await;
+
+    let response = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": "What is six times seven?", "instructions": "Answer in one line" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+
+    assert_eq!(body["object"], "response");
+    assert_eq!(body["status"], "completed");
+    assert!(body["id"].as_str().unwrap().starts_with("resp_"));
+    let output = body["output"].as_array().unwrap();
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[0]["type"], "reasoning");
+    assert_eq!(output[0]["summary"][0]["text"], "Think it through");
+    assert_eq!(output[1]["type"], "message");
+    assert_eq!(output[1]["content"][0]["type"], "output_text");
+    assert_eq!(output[1]["content"][0]["text"], "The answer is 42");
+    assert!(body["usage"]["output_tokens_details"]["reasoning_tokens"].as_u64().unwrap() > 0);
+
+    let crafter = &upstream.requests_for(CRAFT_MODEL)[0];
+    let prompt: String = crafter.messages().map(|(_, content)| content).collect();
+    assert!(prompt.contains("Answer in one line"), "{}", prompt);
+    assert_eq!(crafter.last_user_message(), "What is six times seven?");
+}
+
+#[tokio::test]
+async fn previous_response_id_continues_the_conversation() {
+    let (upstream, server) = setup().await;
+
+    let first: Value = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": [{ "role": "user", "content": [{ "type": "input_text", "text": "Remember 7" }] }] }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let first_id = first["id"].as_str().unwrap();
+
+    let second: Value = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": "Which number?", "previous_response_id": first_id }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(second["previous_response_id"], first_id);
+
+    let crafter = upstream.requests_for(CRAFT_MODEL);
+    let history: Vec<(&str, &str)> = crafter[1].messages().filter(|(role, _)| *role != "system").collect();
+    assert_eq!(
+        history,
+        [("user", "Remember 7"), ("assistant", "The answer is 42"), ("user", "Which number?")]
+    );
+}
+
+#[tokio::test]
+async fn unknown_previous_response_is_rejected() {
+    let (upstream, server) = setup().await;
+
+    let response = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": "Hello", "previous_response_id": "resp_missing" }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn unstored_responses_cannot_be_chained() {
+    let (_upstream, server) = setup().await;
+
+    let first: Value = post_response(&server, json!({ "model": "dualmind", "input": "Hello", "store": false }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let response = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": "Again", "previous_response_id": first["id"] }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn unsupported_input_items_are_rejected() {
+    let (upstream, server) = setup().await;
+
+    let response = post_response(
+        &server,
+        json!({ "model": "dualmind", "input": [{ "type": "function_call_output", "call_id": "c", "output": "{}" }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]["message"].as_str().unwrap().contains("function_call_output"));
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn streaming_sends_typed_events() {
+    let (_upstream, server) = setup().await;
+
+    let text = post_response(&server, json!({ "model": "dualmind", "input": "Hello", "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let events = sse_events(&text);
+
+    for (index, (name, data)) in events.iter().enumerate() {
+        assert_eq!(data["type"], name.as_str());
+        assert_eq!(data["sequence_number"], index);
+    }
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    let position = |name: &str| names.iter().position(|n| *n == name).unwrap_or_else(|| panic!("no {} in {:?}", name, names));
+    assert_eq!(names[0], "response.created");
+    assert!(position("response.reasoning_summary_text.delta") < position("response.output_text.delta"));
+    assert!(position("response.reasoning_summary_text.done") < position("response.output_text.delta"));
+    assert_eq!(names.last(), Some(&"response.completed"));
+
+    let deltas = |name: &str| -> String {
+        events
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, data)| data["delta"].as_str().unwrap())
+            .collect()
+    };
+    assert_eq!(deltas("response.reasoning_summary_text.delta"), "Think it through");
+    assert_eq!(deltas("response.output_text.delta"), "The answer is 42");
+
+    let completed = &events.last().unwrap().1["response"];
+    assert_eq!(completed["status"], "completed");
+    assert_eq!(completed["output"][1]["content"][0]["text"], "The answer is 42");
+}
+
+#[tokio::test]
+async fn upstream_errors_fail_the_response() {
+    let (upstream, server) = setup().await;
+    upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::error(429, "Slow down"));
+    upstream.once(Match::Model(CRAFT_MODEL.into()), MockResponse::error(429, "Slow down"));
+
+    let response = post_response(&server, json!({ "model": "dualmind", "input": "Hello" })).await;
+    assert_eq!(response.status(), 429);
+
+    let text = post_response(&server, json!({ "model": "dualmind", "input": "Hello", "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let (name, data) = sse_events(&text).pop().unwrap();
+    assert_eq!(name, "response.failed");
+    assert_eq!(data["response"]["status"], "failed");
+    assert_eq!(data["response"]["error"]["code"], "rate_limit_exceeded");
+}